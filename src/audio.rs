@@ -1,25 +1,21 @@
-use glib;
 use gtk::prelude::*;
 use gtk::{
     Box, Button, ComboBoxText, Image, Label, Orientation, Scale, ScrolledWindow, Window, WindowType,
 };
 use gtk_layer_shell::LayerShell;
 use serde_json::Value;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::process::Command;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
 
+use crate::config::PanelEdge;
 use crate::utils::exec_command;
 
 #[derive(Debug, Clone)]
 struct AudioDevice {
-    id: i64,
     name: String,
     description: String,
     volume: f64,
-    is_muted: bool,
     is_default: bool,
 }
 
@@ -29,7 +25,6 @@ struct AudioStream {
     app_name: String,
     icon_name: String,
     volume: f64,
-    is_muted: bool,
 }
 
 pub struct AudioMixerPopup {
@@ -39,8 +34,8 @@ pub struct AudioMixerPopup {
     input_combo: ComboBoxText,
     master_slider: Scale,
     streams_box: Box,
-    content_box: Box,
-    trigger_button: Arc<Mutex<Option<gtk::Widget>>>,
+    trigger_button: RefCell<Option<gtk::Widget>>,
+    panel_edge: Cell<PanelEdge>,
     updating_ui: Rc<RefCell<bool>>,
     sinks: Rc<RefCell<Vec<AudioDevice>>>,
     sources: Rc<RefCell<Vec<AudioDevice>>>,
@@ -58,14 +53,9 @@ impl AudioMixerPopup {
         backdrop.set_decorated(false);
         backdrop.set_widget_name("backdrop-window");
 
-        let backdrop_css = gtk::CssProvider::new();
-        backdrop_css
-            .load_from_data(b"#backdrop-window { background: transparent; }")
-            .ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &backdrop_css,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        crate::style::add_builtin(
+            "backdrop",
+            b"#backdrop-window { background: transparent; }",
         );
 
         let window = Window::new(WindowType::Toplevel);
@@ -148,13 +138,7 @@ impl AudioMixerPopup {
         let streams_box = Box::new(Orientation::Vertical, 10);
         content_box.pack_start(&streams_box, false, false, 0);
 
-        let provider = gtk::CssProvider::new();
-        provider.load_from_data(include_bytes!("audio.css")).ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
+        crate::style::add_builtin("audio.css", include_bytes!("audio.css"));
 
         let instance = AudioMixerPopup {
             window,
@@ -163,8 +147,8 @@ impl AudioMixerPopup {
             input_combo,
             master_slider,
             streams_box,
-            content_box,
-            trigger_button: Arc::new(Mutex::new(None)),
+            trigger_button: RefCell::new(None),
+            panel_edge: Cell::new(PanelEdge::Bottom),
             updating_ui: Rc::new(RefCell::new(false)),
             sinks: Rc::new(RefCell::new(Vec::new())),
             sources: Rc::new(RefCell::new(Vec::new())),
@@ -349,13 +333,11 @@ impl AudioMixerPopup {
         if let Ok(json) = serde_json::from_str::<Value>(&sinks_json) {
             if let Some(arr) = json.as_array() {
                 for item in arr {
-                    let id = item["index"].as_i64().unwrap_or(0);
                     let name = item["name"].as_str().unwrap_or("").to_string();
                     let description = item["description"]
                         .as_str()
                         .unwrap_or("Unknown")
                         .to_string();
-                    let is_muted = item["mute"].as_bool().unwrap_or(false);
                     let is_default = name == default_sink;
 
                     let mut volume = 100.0;
@@ -368,11 +350,9 @@ impl AudioMixerPopup {
                     }
 
                     sinks.push(AudioDevice {
-                        id,
                         name,
                         description,
                         volume,
-                        is_muted,
                         is_default,
                     });
                 }
@@ -389,12 +369,10 @@ impl AudioMixerPopup {
                         continue;
                     }
 
-                    let id = item["index"].as_i64().unwrap_or(0);
                     let description = item["description"]
                         .as_str()
                         .unwrap_or("Unknown")
                         .to_string();
-                    let is_muted = item["mute"].as_bool().unwrap_or(false);
                     let is_default = name == default_source;
 
                     let mut volume = 100.0;
@@ -407,11 +385,9 @@ impl AudioMixerPopup {
                     }
 
                     sources.push(AudioDevice {
-                        id,
                         name,
                         description,
                         volume,
-                        is_muted,
                         is_default,
                     });
                 }
@@ -423,7 +399,6 @@ impl AudioMixerPopup {
             if let Some(arr) = json.as_array() {
                 for item in arr {
                     let id = item["index"].as_i64().unwrap_or(0);
-                    let is_muted = item["mute"].as_bool().unwrap_or(false);
 
                     let mut app_name = "Unknown App".to_string();
                     let mut icon_name = "audio-x-generic".to_string();
@@ -457,7 +432,6 @@ impl AudioMixerPopup {
                         app_name,
                         icon_name,
                         volume,
-                        is_muted,
                     });
                 }
             }
@@ -467,8 +441,7 @@ impl AudioMixerPopup {
     }
 
     pub fn set_trigger_button<W: IsA<gtk::Widget>>(&self, button: &W) {
-        let mut btn = self.trigger_button.lock().unwrap();
-        *btn = Some(button.clone().upcast());
+        *self.trigger_button.borrow_mut() = Some(button.clone().upcast());
     }
 
     pub fn toggle(&self) {
@@ -495,13 +468,50 @@ impl AudioMixerPopup {
     }
 
     fn position_window(&self) {
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Bottom, true);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Right, true);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Left, false);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Top, false);
+        let trigger = self.trigger_button.borrow().clone();
+        crate::utils::anchor_popup(
+            &self.window,
+            self.panel_edge.get(),
+            true,
+            trigger.as_ref(),
+        );
     }
+}
 
-    pub fn get_window(&self) -> &Window {
-        &self.window
+impl crate::modules::PanelPopup for AudioMixerPopup {
+    fn toggle(&self) {
+        AudioMixerPopup::toggle(self);
     }
+
+    fn set_trigger(&self, widget: &gtk::Widget) {
+        self.set_trigger_button(widget);
+    }
+
+    fn set_edge(&self, edge: PanelEdge) {
+        self.panel_edge.set(edge);
+    }
+}
+
+fn default_sink_state() -> crate::ipc::Event {
+    let sink = exec_command("pactl get-default-sink").trim().to_string();
+    let volume = exec_command("pactl get-sink-volume @DEFAULT_SINK@")
+        .split_whitespace()
+        .find_map(|word| word.strip_suffix('%').and_then(|v| v.parse().ok()))
+        .unwrap_or(0);
+    let muted = exec_command("pactl get-sink-mute @DEFAULT_SINK@").contains("yes");
+    crate::ipc::Event::Audio {
+        sink,
+        volume,
+        muted,
+    }
+}
+
+// Publishes the default sink whenever it or its volume changes. Sink inputs,
+// the streams of each app, change far more often and are skipped.
+pub fn watch_events() {
+    crate::ipc::watch_command(
+        &["pactl", "subscribe"],
+        |line| line.contains(" on sink #") || line.contains(" on server"),
+        default_sink_state,
+    );
 }
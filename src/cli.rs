@@ -0,0 +1,242 @@
+use crate::ipc::{self, Request, WindowInfo};
+
+pub enum Command {
+    Run { config: Option<String> },
+    Msg { request: Request, json: bool },
+    Help,
+}
+
+pub const USAGE: &str = "Usage:
+  labar [--config <path>]          Run the panel
+  labar msg <command> [--json]     Send a command to the running panel
+
+Commands:
+  toggle-launcher
+  toggle-wifi
+  toggle-audio
+  reload-config
+  windows
+  activate-window <id>
+  show-desktop
+  thumbnail <id> <file.png>
+  subscribe [window|keyboard|audio|network ...]";
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut iter = args.iter();
+    let mut config = None;
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-c" | "--config" => match iter.next() {
+                Some(path) => config = Some(path.clone()),
+                None => return Err("--config needs a path".to_string()),
+            },
+            "msg" => {
+                let rest: Vec<String> = iter.cloned().collect();
+                return parse_msg(&rest);
+            }
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+
+    Ok(Command::Run { config })
+}
+
+fn parse_msg(args: &[String]) -> Result<Command, String> {
+    let json = args.iter().any(|a| a == "--json");
+    let words: Vec<&str> = args
+        .iter()
+        .filter(|a| *a != "--json")
+        .map(|a| a.as_str())
+        .collect();
+
+    let request = match words.as_slice() {
+        ["toggle-launcher"] => Request::ToggleLauncher,
+        ["toggle-wifi"] => Request::ToggleWifi,
+        ["toggle-audio"] => Request::ToggleAudio,
+        ["reload-config"] => Request::ReloadConfig,
+        ["windows"] | ["list-windows"] => Request::ListWindows,
+        ["activate-window", id] => Request::ActivateWindow {
+            id: id
+                .parse()
+                .map_err(|_| format!("invalid window id: {}", id))?,
+        },
+        ["activate-window"] => return Err("activate-window needs a window id".to_string()),
+        ["show-desktop"] => Request::ShowDesktop,
+        ["thumbnail", id, path] => Request::Thumbnail {
+            id: id
+                .parse()
+                .map_err(|_| format!("invalid window id: {}", id))?,
+            // The panel runs in a different directory, so resolve the path here.
+            path: std::path::absolute(path)
+                .map_err(|e| format!("invalid path {}: {}", path, e))?
+                .to_string_lossy()
+                .to_string(),
+        },
+        ["thumbnail", ..] => return Err("thumbnail needs a window id and a file".to_string()),
+        ["subscribe", kinds @ ..] => {
+            if let Some(kind) = kinds.iter().find(|k| !ipc::EVENT_KINDS.contains(k)) {
+                return Err(format!("unknown event kind: {}", kind));
+            }
+            Request::Subscribe {
+                events: kinds.iter().map(|k| k.to_string()).collect(),
+            }
+        }
+        [] => return Err("msg needs a command".to_string()),
+        [other, ..] => return Err(format!("unknown command: {}", other)),
+    };
+
+    Ok(Command::Msg { request, json })
+}
+
+pub fn run_msg(request: &Request, json: bool) -> i32 {
+    let path = match ipc::socket_path() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("labar: {}", e);
+            return 1;
+        }
+    };
+
+    if let Request::Subscribe { .. } = request {
+        return match ipc::stream_events(&path, request) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("labar: {}", e);
+                1
+            }
+        };
+    }
+
+    let response = match ipc::send(&path, request) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("labar: {}", e);
+            return 1;
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&response).unwrap_or_default());
+        return if response.ok { 0 } else { 1 };
+    }
+
+    if !response.ok {
+        eprintln!("labar: {}", response.error.unwrap_or_default());
+        return 1;
+    }
+
+    if let Request::ListWindows = request {
+        let windows: Vec<WindowInfo> = response
+            .data
+            .and_then(|data| serde_json::from_value(data).ok())
+            .unwrap_or_default();
+        for win in windows {
+            let mut flags = String::new();
+            if win.activated {
+                flags.push('*');
+            }
+            if win.minimized {
+                flags.push('_');
+            }
+            println!("{}\t{}\t{}\t{}", win.id, flags, win.app_id, win.title);
+        }
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    fn msg(line: &str) -> Result<(Request, bool), String> {
+        match parse(&args(line))? {
+            Command::Msg { request, json } => Ok((request, json)),
+            _ => panic!("{} is not a msg command", line),
+        }
+    }
+
+    #[test]
+    fn parses_run_and_help() {
+        assert!(matches!(parse(&[]), Ok(Command::Run { config: None })));
+        assert!(matches!(
+            parse(&args("--config /tmp/labar.json")),
+            Ok(Command::Run { config: Some(path) }) if path == "/tmp/labar.json"
+        ));
+        assert!(matches!(parse(&args("-h")), Ok(Command::Help)));
+        assert!(parse(&args("--config")).is_err());
+        assert!(parse(&args("--verbose")).is_err());
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(
+            msg("msg toggle-launcher"),
+            Ok((Request::ToggleLauncher, false))
+        ));
+        assert!(matches!(
+            msg("msg reload-config --json"),
+            Ok((Request::ReloadConfig, true))
+        ));
+        assert!(matches!(
+            msg("msg windows"),
+            Ok((Request::ListWindows, false))
+        ));
+        assert!(matches!(
+            msg("msg list-windows"),
+            Ok((Request::ListWindows, false))
+        ));
+        assert!(matches!(
+            msg("msg --json activate-window 42"),
+            Ok((Request::ActivateWindow { id: 42 }, true))
+        ));
+        match msg("msg thumbnail 7 /tmp/thumb.png") {
+            Ok((Request::Thumbnail { id, path }, false)) => {
+                assert_eq!(id, 7);
+                assert_eq!(path, "/tmp/thumb.png");
+            }
+            _ => panic!("thumbnail not parsed"),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_window_ids() {
+        assert!(msg("msg activate-window").is_err());
+        assert!(msg("msg activate-window firefox").is_err());
+        assert!(msg("msg activate-window -1").is_err());
+        assert!(msg("msg thumbnail x /tmp/thumb.png").is_err());
+        assert!(msg("msg thumbnail 7").is_err());
+    }
+
+    #[test]
+    fn parses_subscribe_kinds() {
+        match msg("msg subscribe") {
+            Ok((Request::Subscribe { events }, false)) => assert!(events.is_empty()),
+            _ => panic!("subscribe not parsed"),
+        }
+        match msg("msg subscribe window audio") {
+            Ok((Request::Subscribe { events }, false)) => assert_eq!(events, ["window", "audio"]),
+            _ => panic!("subscribe not parsed"),
+        }
+        assert_eq!(
+            msg("msg subscribe window clipboard").err().as_deref(),
+            Some("unknown event kind: clipboard")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert!(msg("msg").is_err());
+        assert!(msg("msg --json").is_err());
+        assert_eq!(
+            msg("msg toggle-everything").err().as_deref(),
+            Some("unknown command: toggle-everything")
+        );
+    }
+}
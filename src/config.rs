@@ -0,0 +1,218 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl PanelEdge {
+    pub fn layer_edge(&self) -> gtk_layer_shell::Edge {
+        match self {
+            PanelEdge::Top => gtk_layer_shell::Edge::Top,
+            PanelEdge::Bottom => gtk_layer_shell::Edge::Bottom,
+            PanelEdge::Left => gtk_layer_shell::Edge::Left,
+            PanelEdge::Right => gtk_layer_shell::Edge::Right,
+        }
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        matches!(self, PanelEdge::Left | PanelEdge::Right)
+    }
+
+    pub fn orientation(&self) -> gtk::Orientation {
+        if self.is_vertical() {
+            gtk::Orientation::Vertical
+        } else {
+            gtk::Orientation::Horizontal
+        }
+    }
+
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            PanelEdge::Top => "edge-top",
+            PanelEdge::Bottom => "edge-bottom",
+            PanelEdge::Left => "edge-left",
+            PanelEdge::Right => "edge-right",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Margins {
+    pub top: i32,
+    pub bottom: i32,
+    pub left: i32,
+    pub right: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ModuleLayout {
+    pub left: Vec<String>,
+    pub center: Vec<String>,
+    pub right: Vec<String>,
+}
+
+impl Default for ModuleLayout {
+    fn default() -> Self {
+        ModuleLayout {
+            left: Vec::new(),
+            center: vec![
+                "launcher".to_string(),
+                "pinned".to_string(),
+                "taskbar".to_string(),
+            ],
+            right: vec![
+                "keyboard".to_string(),
+                "wifi".to_string(),
+                "audio".to_string(),
+                "clock".to_string(),
+                "show-desktop".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub height: i32,
+    pub edge: PanelEdge,
+    pub margins: Margins,
+    // None keeps the zone sized to the panel, like auto_exclusive_zone_enable.
+    pub exclusive_zone: Option<i32>,
+    pub icon_size: i32,
+    pub modules: ModuleLayout,
+    // Per-module settings keyed by module name, e.g. "clock": { "format": "%H:%M" }.
+    pub module_config: HashMap<String, serde_json::Value>,
+    // app_id -> desktop file id, for apps the automatic lookup gets wrong.
+    pub desktop_entries: HashMap<String, String>,
+    // Restart the panel after it loses the compositor, see supervisor.rs.
+    pub reconnect: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            height: 60,
+            edge: PanelEdge::Bottom,
+            margins: Margins::default(),
+            exclusive_zone: None,
+            icon_size: 29,
+            modules: ModuleLayout::default(),
+            module_config: HashMap::new(),
+            desktop_entries: HashMap::new(),
+            reconnect: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> String {
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        format!("{}/.config/labar/config.json", home)
+    }
+
+    pub fn load(path: &str) -> Self {
+        match fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<Config>(&content) {
+                Ok(config) => return config,
+                Err(e) => eprintln!("[Config] Failed to parse {}: {}", path, e),
+            },
+            Err(_) => eprintln!("[Config] No config at {}, using defaults", path),
+        }
+
+        Config::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<Config, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn empty_object_gives_defaults() {
+        let config = parse("{}").unwrap();
+        assert_eq!(config.height, 60);
+        assert_eq!(config.edge, PanelEdge::Bottom);
+        assert_eq!(config.margins.top, 0);
+        assert_eq!(config.exclusive_zone, None);
+        assert_eq!(config.icon_size, 29);
+        assert!(config.modules.left.is_empty());
+        assert_eq!(config.modules.center, ["launcher", "pinned", "taskbar"]);
+        assert_eq!(
+            config.modules.right,
+            ["keyboard", "wifi", "audio", "clock", "show-desktop"]
+        );
+        assert!(config.module_config.is_empty());
+        assert!(config.desktop_entries.is_empty());
+        assert!(!config.reconnect);
+    }
+
+    #[test]
+    fn missing_keys_keep_their_defaults() {
+        let config = parse(
+            r#"{
+                "edge": "left",
+                "margins": { "left": 4 },
+                "modules": { "right": ["clock"] },
+                "exclusive_zone": 0
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.edge, PanelEdge::Left);
+        assert!(config.edge.is_vertical());
+        assert_eq!(config.margins.left, 4);
+        assert_eq!(config.margins.top, 0);
+        assert_eq!(config.modules.right, ["clock"]);
+        assert_eq!(config.modules.center, ["launcher", "pinned", "taskbar"]);
+        assert_eq!(config.exclusive_zone, Some(0));
+        assert_eq!(config.height, 60);
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let config =
+            parse(r#"{ "colour": "red", "margins": { "middle": 3 }, "height": 40 }"#).unwrap();
+        assert_eq!(config.height, 40);
+        assert_eq!(config.margins.left, 0);
+    }
+
+    #[test]
+    fn module_sections_are_kept_as_json() {
+        let config = parse(r#"{ "module_config": { "clock": { "format": "%H:%M" } } }"#).unwrap();
+        assert_eq!(config.module_config["clock"]["format"], "%H:%M");
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(parse(r#"{ "edge": "middle" }"#).is_err());
+        assert!(parse(r#"{ "edge": "Top" }"#).is_err());
+        assert!(parse(r#"{ "margins": { "top": "10" } }"#).is_err());
+        assert!(parse(r#"{ "margins": { "top": 2.5 } }"#).is_err());
+        assert!(parse(r#"{ "height": -1e12 }"#).is_err());
+    }
+
+    #[test]
+    fn load_falls_back_to_defaults() {
+        let path = std::env::temp_dir().join(format!("labar-config-{}.json", std::process::id()));
+        fs::write(&path, r#"{ "edge": "middle" }"#).unwrap();
+        let config = Config::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.edge, PanelEdge::Bottom);
+
+        let config = Config::load("/nonexistent/labar/config.json");
+        assert_eq!(config.height, 60);
+    }
+}
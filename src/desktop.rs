@@ -0,0 +1,202 @@
+use gio::prelude::*;
+use gio::DesktopAppInfo;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Maps toplevel app_ids to desktop entries. Lookups scan every installed
+// entry, so results (including misses) are cached until the installed
+// applications or the user overrides change.
+#[derive(Default)]
+struct Resolver {
+    overrides: HashMap<String, String>,
+    cache: HashMap<String, Option<DesktopAppInfo>>,
+    watching: bool,
+}
+
+thread_local! {
+    static RESOLVER: RefCell<Resolver> = RefCell::new(Resolver::default());
+}
+
+// `desktop_entries` from the config: app_id -> desktop id, for apps whose
+// app_id matches nothing, e.g. "code-url-handler": "code.desktop".
+pub fn set_overrides(overrides: &HashMap<String, String>) {
+    RESOLVER.with(|r| {
+        let mut r = r.borrow_mut();
+        r.overrides = overrides.clone();
+        r.cache.clear();
+        if !r.watching {
+            r.watching = true;
+            let monitor = gio::AppInfoMonitor::get();
+            monitor.connect_changed(|_| {
+                RESOLVER.with(|r| r.borrow_mut().cache.clear());
+            });
+            // The monitor only emits while a reference is alive.
+            std::mem::forget(monitor);
+        }
+    });
+}
+
+fn desktop_id(name: &str) -> String {
+    if name.ends_with(".desktop") {
+        name.to_string()
+    } else {
+        format!("{}.desktop", name)
+    }
+}
+
+fn by_id(name: &str) -> Option<DesktopAppInfo> {
+    DesktopAppInfo::new(&desktop_id(name))
+}
+
+// Lower is better; None when the entry has nothing to do with the app_id.
+fn rank(info: &DesktopAppInfo, app_id: &str) -> Option<u8> {
+    let id = info.id()?.to_string();
+    let stem = id.trim_end_matches(".desktop").to_lowercase();
+
+    if stem == app_id {
+        return Some(0);
+    }
+    // Reverse-DNS ids on either side: org.gnome.Nautilus <-> nautilus.
+    let last = |s: &str| s.rsplit('.').next().unwrap_or_default().to_string();
+    if last(&stem) == app_id || stem == last(app_id) {
+        return Some(1);
+    }
+    if info
+        .startup_wm_class()
+        .is_some_and(|class| class.to_lowercase() == app_id)
+    {
+        return Some(2);
+    }
+    if info
+        .string("X-Flatpak")
+        .is_some_and(|flatpak| flatpak.to_lowercase() == app_id)
+    {
+        return Some(3);
+    }
+    let exec = info.executable();
+    if exec
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().to_lowercase() == app_id)
+    {
+        return Some(4);
+    }
+    None
+}
+
+fn resolve(app_id: &str, overrides: &HashMap<String, String>) -> Option<DesktopAppInfo> {
+    if app_id.is_empty() {
+        return None;
+    }
+    if let Some(target) = overrides.get(app_id) {
+        match by_id(target) {
+            Some(info) => return Some(info),
+            None => eprintln!(
+                "[Desktop] Override for {}: no entry named {}",
+                app_id, target
+            ),
+        }
+    }
+    if let Some(info) = by_id(app_id).or_else(|| by_id(&app_id.to_lowercase())) {
+        return Some(info);
+    }
+
+    let app_id = app_id.to_lowercase();
+    gio::AppInfo::all()
+        .into_iter()
+        .filter_map(|app| app.downcast::<DesktopAppInfo>().ok())
+        .filter_map(|info| rank(&info, &app_id).map(|r| (r, info)))
+        .min_by_key(|(r, _)| *r)
+        .map(|(_, info)| info)
+}
+
+pub fn lookup(app_id: &str) -> Option<DesktopAppInfo> {
+    RESOLVER.with(|r| {
+        let mut r = r.borrow_mut();
+        if let Some(cached) = r.cache.get(app_id) {
+            return cached.clone();
+        }
+        let info = resolve(app_id, &r.overrides);
+        r.cache.insert(app_id.to_string(), info.clone());
+        info
+    })
+}
+
+// The entry's own icon, or a themed icon named after the app_id.
+pub fn icon(app_id: &str) -> gio::Icon {
+    lookup(app_id)
+        .and_then(|info| info.icon())
+        .unwrap_or_else(|| {
+            gio::ThemedIcon::from_names(&[
+                app_id,
+                &app_id.to_lowercase(),
+                "application-x-executable",
+            ])
+            .upcast()
+        })
+}
+
+// The app_id windows of this entry are expected to carry.
+pub fn app_id_of(info: &DesktopAppInfo) -> String {
+    if let Some(class) = info.startup_wm_class() {
+        return class.to_string();
+    }
+    info.id()
+        .map(|id| id.trim_end_matches(".desktop").to_string())
+        .unwrap_or_default()
+}
+
+fn launch_context() -> Option<gdk::AppLaunchContext> {
+    gdk::Display::default()?.app_launch_context()
+}
+
+// Runs the entry's Exec line as a new process. A plain launch of a
+// D-Bus activatable app only activates, and so raises, the running instance.
+fn launch_new_process(info: &DesktopAppInfo) -> Result<(), glib::Error> {
+    let Some(commandline) = info.commandline() else {
+        return info.launch(&[], launch_context().as_ref());
+    };
+    let flags = if info.boolean("Terminal") {
+        gio::AppInfoCreateFlags::NEEDS_TERMINAL
+    } else {
+        gio::AppInfoCreateFlags::NONE
+    };
+    let app = gio::AppInfo::create_from_commandline(commandline, Some(&info.name()), flags)?;
+    app.launch(&[], launch_context().as_ref())
+}
+
+// "New window" plus the entry's own desktop actions (Actions= in the .desktop
+// file), followed by a separator. Adds nothing when the app has no entry.
+pub fn append_actions(menu: &gtk::Menu, app_id: &str) {
+    use gtk::prelude::*;
+
+    let Some(info) = lookup(app_id) else {
+        return;
+    };
+    let actions: Vec<String> = info.list_actions().iter().map(|a| a.to_string()).collect();
+
+    let new_window = gtk::MenuItem::with_label(&crate::locales::LOCALE.new_window);
+    let info_new = info.clone();
+    // Prefer the app's own new-window action over starting another process.
+    let own_action = actions.iter().find(|a| a.as_str() == "new-window").cloned();
+    new_window.connect_activate(move |_| match own_action.as_deref() {
+        Some(action) => info_new.launch_action(action, launch_context().as_ref()),
+        None => {
+            if let Err(e) = launch_new_process(&info_new) {
+                eprintln!("[Desktop] Failed to launch {:?}: {}", info_new.id(), e);
+            }
+        }
+    });
+    menu.append(&new_window);
+
+    for action in actions.iter().filter(|a| a.as_str() != "new-window") {
+        let item = gtk::MenuItem::with_label(&info.action_name(action));
+        let info_action = info.clone();
+        let action = action.clone();
+        item.connect_activate(move |_| {
+            info_action.launch_action(&action, launch_context().as_ref());
+        });
+        menu.append(&item);
+    }
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+}
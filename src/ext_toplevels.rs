@@ -0,0 +1,309 @@
+use wayland_client::globals::GlobalList;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+    ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+};
+
+use crate::wayland::{report_closed, report_done, AppData, WindowHandle};
+
+// ext-foreign-toplevel-list-v1 only lists windows. When it is the only
+// toplevel protocol, its handles become the windows and labar is read-only.
+// Next to wlr-foreign-toplevel-management the wlr handles stay the windows,
+// and each one is linked to its ext handle for the stable identifier and for
+// capturing.
+#[derive(Debug, Clone)]
+pub struct ExtToplevel {
+    pub handle: ExtForeignToplevelHandleV1,
+    pub identifier: String,
+    pub title: String,
+    pub app_id: String,
+    // Set after the first `done`, once title and app_id are known.
+    pub done: bool,
+}
+
+pub fn bind(globals: &GlobalList, qh: &QueueHandle<AppData>) -> Option<ExtForeignToplevelListV1> {
+    match globals.bind::<ExtForeignToplevelListV1, _, _>(qh, 1..=1, ()) {
+        Ok(list) => {
+            eprintln!("[Wayland] Bound ext-foreign-toplevel-list.");
+            Some(list)
+        }
+        Err(_) => None,
+    }
+}
+
+// Neither protocol says which wlr handle is which ext handle, so windows are
+// paired by app_id and title. Matching the app_id alone is not enough: the
+// two sides of a new window arrive in separate batches, so the one free ext
+// handle may belong to another window of the app. A window stays unlinked
+// while the match is ambiguous; both sides call this again on title changes.
+fn link(windows: &mut [WindowHandle], toplevels: &[ExtToplevel]) {
+    let found = {
+        let free_windows: Vec<Option<(&str, &str)>> = windows
+            .iter()
+            .map(|w| (w.ext.is_none() && w.announced).then_some((&*w.app_id, &*w.title)))
+            .collect();
+        let free_toplevels: Vec<Option<(&str, &str)>> = toplevels
+            .iter()
+            .map(|t| {
+                let linked = windows.iter().any(|w| w.ext.as_ref() == Some(&t.handle));
+                (t.done && !linked).then_some((&*t.app_id, &*t.title))
+            })
+            .collect();
+        pairs(&free_windows, &free_toplevels)
+    };
+    for (window, toplevel) in found {
+        windows[window].ext = Some(toplevels[toplevel].handle.clone());
+        windows[window].identifier = Some(toplevels[toplevel].identifier.clone());
+    }
+}
+
+// The (app_id, title) of each window and ext toplevel, None for those that are
+// already linked or not complete yet. Returns the window and toplevel indices
+// to link: a window and a toplevel are paired when each is the only free one
+// with that app_id and title.
+fn pairs(
+    windows: &[Option<(&str, &str)>],
+    toplevels: &[Option<(&str, &str)>],
+) -> Vec<(usize, usize)> {
+    let mut taken = vec![false; toplevels.len()];
+    let mut found = Vec::new();
+    for (window, key) in windows.iter().enumerate() {
+        let Some(key) = key else {
+            continue;
+        };
+        if windows.iter().filter(|w| w.as_ref() == Some(key)).count() > 1 {
+            continue;
+        }
+        let matching: Vec<usize> = toplevels
+            .iter()
+            .enumerate()
+            .filter(|(index, toplevel)| !taken[*index] && toplevel.as_ref() == Some(key))
+            .map(|(index, _)| index)
+            .collect();
+        if let [toplevel] = matching[..] {
+            taken[toplevel] = true;
+            found.push((window, toplevel));
+        }
+    }
+    found
+}
+
+// The wlr manager went away while the ext list still runs. Windows linked to
+// an ext handle carry on read-only under the same id, the others are gone, and
+// ext toplevels that were never linked become windows of their own.
+pub fn take_over(state: &mut AppData) {
+    let windows_arc = state.windows.clone();
+    let mut windows = windows_arc.lock().unwrap();
+    for mut win in std::mem::take(&mut *windows) {
+        if win.ext.is_none() {
+            report_closed(&state.ui_sender, win);
+            continue;
+        }
+        // The ext list reports no state or outputs.
+        win.wlr = None;
+        win.minimized = false;
+        win.activated = false;
+        win.maximized = false;
+        win.fullscreen = false;
+        win.outputs.clear();
+        win.pending.state = true;
+        win.pending.outputs = true;
+        report_done(&state.ui_sender, &mut win);
+        windows.push(win);
+    }
+
+    for toplevel in &state.ext_toplevels {
+        if windows
+            .iter()
+            .any(|w| w.ext.as_ref() == Some(&toplevel.handle))
+        {
+            continue;
+        }
+        let id = state.next_toplevel_id;
+        state.next_toplevel_id += 1;
+        let mut win = WindowHandle {
+            id,
+            ext: Some(toplevel.handle.clone()),
+            identifier: Some(toplevel.identifier.clone()),
+            title: toplevel.title.clone(),
+            app_id: toplevel.app_id.clone(),
+            ..WindowHandle::default()
+        };
+        if toplevel.done {
+            report_done(&state.ui_sender, &mut win);
+        }
+        windows.push(win);
+    }
+}
+
+// Called after the wlr side of a window changed.
+pub fn relink(state: &AppData) {
+    if state.ext_toplevels.is_empty() {
+        return;
+    }
+    let mut windows = state.windows.lock().unwrap();
+    link(&mut windows, &state.ext_toplevels);
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for AppData {
+    fn event(
+        state: &mut AppData,
+        _proxy: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<AppData>,
+    ) {
+        match event {
+            ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } => {
+                if state.manager.is_none() {
+                    let id = state.next_toplevel_id;
+                    state.next_toplevel_id += 1;
+                    eprintln!("[Wayland] New ext toplevel found: ID={}", id);
+                    state.windows.lock().unwrap().push(WindowHandle {
+                        id,
+                        ext: Some(toplevel.clone()),
+                        ..WindowHandle::default()
+                    });
+                }
+                state.ext_toplevels.push(ExtToplevel {
+                    handle: toplevel,
+                    identifier: String::new(),
+                    title: String::new(),
+                    app_id: String::new(),
+                    done: false,
+                });
+            }
+            ext_foreign_toplevel_list_v1::Event::Finished => {
+                eprintln!("[Wayland] ext-foreign-toplevel-list finished");
+            }
+            _ => {}
+        }
+    }
+
+    wayland_client::event_created_child!(AppData, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for AppData {
+    fn event(
+        state: &mut AppData,
+        proxy: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<AppData>,
+    ) {
+        let Some(index) = state.ext_toplevels.iter().position(|t| &t.handle == proxy) else {
+            return;
+        };
+
+        if let ext_foreign_toplevel_handle_v1::Event::Closed = event {
+            state.ext_toplevels.remove(index);
+            let mut windows = state.windows.lock().unwrap();
+            if let Some(pos) = windows.iter().position(|w| w.ext.as_ref() == Some(proxy)) {
+                if windows[pos].wlr.is_some() {
+                    windows[pos].ext = None;
+                    windows[pos].identifier = None;
+                } else {
+                    report_closed(&state.ui_sender, windows.remove(pos));
+                }
+            }
+            proxy.destroy();
+            return;
+        }
+
+        let toplevel = &mut state.ext_toplevels[index];
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Identifier { identifier } => {
+                toplevel.identifier = identifier;
+            }
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => {
+                toplevel.title = title;
+            }
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                toplevel.app_id = app_id;
+            }
+            ext_foreign_toplevel_handle_v1::Event::Done => {
+                toplevel.done = true;
+                let toplevel = toplevel.clone();
+                let mut windows = state.windows.lock().unwrap();
+                match windows
+                    .iter_mut()
+                    .find(|w| w.wlr.is_none() && w.ext.as_ref() == Some(proxy))
+                {
+                    // Read-only: the ext handle is the window.
+                    Some(win) => {
+                        win.identifier = Some(toplevel.identifier);
+                        if win.title != toplevel.title {
+                            win.title = toplevel.title;
+                            win.pending.title = true;
+                        }
+                        if win.app_id != toplevel.app_id {
+                            win.app_id = toplevel.app_id;
+                            win.pending.app_id = true;
+                        }
+                        report_done(&state.ui_sender, win);
+                    }
+                    None => link(&mut windows, &state.ext_toplevels),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unique_matches_are_paired() {
+        let windows = [Some(("firefox", "Mail")), Some(("foot", "~"))];
+        let toplevels = [Some(("foot", "~")), Some(("firefox", "Mail"))];
+        assert_eq!(pairs(&windows, &toplevels), [(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn app_id_alone_is_not_a_match() {
+        let windows = [Some(("foot", "~"))];
+        let toplevels = [Some(("foot", "vim"))];
+        assert!(pairs(&windows, &toplevels).is_empty());
+    }
+
+    #[test]
+    fn linked_and_incomplete_sides_are_skipped() {
+        let windows = [None, Some(("foot", "~"))];
+        let toplevels = [None, Some(("foot", "~"))];
+        assert_eq!(pairs(&windows, &toplevels), [(1, 1)]);
+
+        let toplevels = [Some(("foot", "~")), None];
+        assert_eq!(pairs(&[None], &toplevels), []);
+    }
+
+    #[test]
+    fn ambiguous_pairs_stay_unlinked() {
+        let windows = [Some(("foot", "~")), Some(("foot", "~"))];
+        let toplevels = [Some(("foot", "~")), Some(("foot", "~"))];
+        assert!(pairs(&windows, &toplevels).is_empty());
+
+        // One window, but two toplevels it could be, and the reverse.
+        assert!(pairs(&windows[..1], &toplevels).is_empty());
+        assert!(pairs(&windows, &toplevels[..1]).is_empty());
+    }
+
+    #[test]
+    fn title_change_resolves_ambiguity() {
+        let toplevels = [Some(("foot", "vim")), Some(("foot", "~"))];
+        let windows = [Some(("foot", "~")), Some(("foot", "~"))];
+        assert!(pairs(&windows, &toplevels[1..]).is_empty());
+
+        // The first window's title changed on both sides, so each window now
+        // has exactly one candidate.
+        let windows = [Some(("foot", "vim")), Some(("foot", "~"))];
+        assert_eq!(pairs(&windows, &toplevels), [(0, 0), (1, 1)]);
+    }
+}
@@ -0,0 +1,448 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::{mpsc, Mutex, Once};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::panel::PanelManager;
+use crate::wayland::ToplevelId;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    ToggleLauncher,
+    ToggleWifi,
+    ToggleAudio,
+    ReloadConfig,
+    #[serde(alias = "windows")]
+    ListWindows,
+    ActivateWindow { id: ToplevelId },
+    ShowDesktop,
+    Thumbnail { id: ToplevelId, path: String },
+    Subscribe {
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(data: Option<serde_json::Value>) -> Self {
+        Response {
+            ok: true,
+            data,
+            error: None,
+        }
+    }
+
+    pub fn error(message: &str) -> Self {
+        Response {
+            ok: false,
+            data: None,
+            error: Some(message.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WindowInfo {
+    pub id: ToplevelId,
+    pub title: String,
+    pub app_id: String,
+    pub minimized: bool,
+    pub activated: bool,
+    #[serde(default)]
+    pub maximized: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
+    // Stable ext-foreign-toplevel-list identifier, when the compositor has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    WindowNew {
+        id: ToplevelId,
+        title: String,
+        app_id: String,
+    },
+    WindowClosed {
+        id: ToplevelId,
+    },
+    WindowTitle {
+        id: ToplevelId,
+        title: String,
+    },
+    WindowActivated {
+        id: ToplevelId,
+        app_id: String,
+    },
+    KeyboardLayout {
+        layout: String,
+    },
+    Audio {
+        sink: String,
+        volume: u32,
+        muted: bool,
+    },
+    Network {
+        state: String,
+        ssid: Option<String>,
+    },
+}
+
+// Every value Event::kind returns, for checking subscribe requests.
+pub const EVENT_KINDS: &[&str] = &["window", "keyboard", "audio", "network"];
+
+impl Event {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::WindowNew { .. }
+            | Event::WindowClosed { .. }
+            | Event::WindowTitle { .. }
+            | Event::WindowActivated { .. } => "window",
+            Event::KeyboardLayout { .. } => "keyboard",
+            Event::Audio { .. } => "audio",
+            Event::Network { .. } => "network",
+        }
+    }
+}
+
+struct Subscriber {
+    stream: UnixStream,
+    kinds: Vec<String>,
+}
+
+impl Subscriber {
+    fn wants(&self, kind: &str) -> bool {
+        self.kinds.is_empty() || self.kinds.iter().any(|k| k == kind)
+    }
+
+    fn send(&mut self, event: &Event) -> bool {
+        let json = serde_json::to_string(event).unwrap_or_default();
+        writeln!(self.stream, "{}", json).is_ok()
+    }
+}
+
+enum Outgoing {
+    Event(Event),
+    Subscribe(Subscriber),
+}
+
+lazy_static! {
+    // The writer thread owns the subscriber sockets, so a client that stops
+    // reading only ever stalls that thread, never Wayland dispatch or GTK.
+    static ref OUTGOING: Mutex<mpsc::Sender<Outgoing>> = {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || write_events(receiver));
+        Mutex::new(sender)
+    };
+}
+
+static WATCHERS: Once = Once::new();
+
+fn write_events(receiver: mpsc::Receiver<Outgoing>) {
+    let mut subscribers: Vec<Subscriber> = Vec::new();
+    // Latest audio, network and keyboard state, for subscribers that join
+    // after it was published.
+    let mut state: Vec<Event> = Vec::new();
+
+    for message in receiver {
+        match message {
+            Outgoing::Subscribe(mut sub) => {
+                let current: Vec<&Event> = state.iter().filter(|e| sub.wants(e.kind())).collect();
+                if current.into_iter().all(|event| sub.send(event)) {
+                    subscribers.push(sub);
+                }
+            }
+            Outgoing::Event(event) => {
+                let kind = event.kind();
+                if kind != "window" {
+                    state.retain(|e| e.kind() != kind);
+                    state.push(event.clone());
+                }
+                // Clients that stop reading are dropped once the write timeout hits.
+                subscribers.retain_mut(|sub| !sub.wants(kind) || sub.send(&event));
+            }
+        }
+    }
+}
+
+// Called from the Wayland thread and the watcher threads, never from GTK.
+// Only queues the event; the writer thread sends it.
+pub fn publish(event: Event) {
+    OUTGOING.lock().unwrap().send(Outgoing::Event(event)).ok();
+}
+
+fn add_subscriber(mut stream: UnixStream, kinds: Vec<String>) {
+    stream.set_write_timeout(Some(Duration::from_secs(1))).ok();
+    let ack = serde_json::to_string(&Response::ok(None)).unwrap_or_default();
+    if writeln!(stream, "{}", ack).is_err() {
+        return;
+    }
+
+    // The writer sends the current state first; the first subscriber gets it
+    // from the watchers as they start.
+    OUTGOING
+        .lock()
+        .unwrap()
+        .send(Outgoing::Subscribe(Subscriber { stream, kinds }))
+        .ok();
+
+    WATCHERS.call_once(|| {
+        crate::audio::watch_events();
+        crate::wifi::watch_events();
+    });
+}
+
+// Runs a monitoring command such as `pactl subscribe` on its own thread and
+// publishes `state()` first and then whenever it differs after a line that
+// `relevant` accepts. Only started once a client subscribes.
+pub fn watch_command(
+    command: &'static [&'static str],
+    relevant: fn(&str) -> bool,
+    state: fn() -> Event,
+) {
+    thread::spawn(move || {
+        let name = command.join(" ");
+        let mut child = match Command::new(command[0])
+            .args(&command[1..])
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("[IPC] Cannot run {}: {}", name, e);
+                return;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return;
+        };
+
+        let mut last = state();
+        publish(last.clone());
+
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !relevant(&line) {
+                continue;
+            }
+            let current = state();
+            if current != last {
+                publish(current.clone());
+                last = current;
+            }
+        }
+
+        match child.wait() {
+            Ok(status) => eprintln!("[IPC] {} exited ({}), events stopped", name, status),
+            Err(e) => eprintln!("[IPC] Failed to wait for {}: {}", name, e),
+        }
+    });
+}
+
+pub struct IpcCall {
+    pub request: Request,
+    pub reply: mpsc::Sender<Response>,
+}
+
+// Only the user's runtime directory is private. In a shared directory such
+// as /tmp another user could take the path first, and `thumbnail` writes
+// files wherever the client asks.
+pub fn socket_path() -> Result<String, String> {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => Ok(format!("{}/labar.sock", dir)),
+        _ => Err("XDG_RUNTIME_DIR is not set".to_string()),
+    }
+}
+
+pub fn send(path: &str, request: &Request) -> Result<Response, String> {
+    let mut stream =
+        UnixStream::connect(path).map_err(|e| format!("cannot connect to {}: {}", path, e))?;
+    let json = serde_json::to_string(request).map_err(|e| e.to_string())?;
+    writeln!(stream, "{}", json).map_err(|e| e.to_string())?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&line).map_err(|e| format!("invalid response: {}", e))
+}
+
+pub fn stream_events(path: &str, request: &Request) -> Result<(), String> {
+    let mut stream =
+        UnixStream::connect(path).map_err(|e| format!("cannot connect to {}: {}", path, e))?;
+    let json = serde_json::to_string(request).map_err(|e| e.to_string())?;
+    writeln!(stream, "{}", json).map_err(|e| e.to_string())?;
+
+    let mut lines = BufReader::new(stream).lines();
+    let ack = lines.next().ok_or("connection closed")?.map_err(|e| e.to_string())?;
+    let response: Response =
+        serde_json::from_str(&ack).map_err(|e| format!("invalid response: {}", e))?;
+    if !response.ok {
+        return Err(response.error.unwrap_or_default());
+    }
+
+    for line in lines {
+        let line = line.map_err(|e| e.to_string())?;
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// Returns the socket path while listening, for stop() to remove it.
+pub fn start(sender: glib::Sender<IpcCall>) -> Option<String> {
+    let path = match socket_path() {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("[IPC] {}, not listening", e);
+            return None;
+        }
+    };
+
+    if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+        if UnixStream::connect(&path).is_ok() {
+            eprintln!("[IPC] Another instance owns {}, not listening", path);
+            return None;
+        }
+        // Only a stale socket is ours to replace.
+        if !metadata.file_type().is_socket() {
+            eprintln!("[IPC] {} is not a socket, not listening", path);
+            return None;
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[IPC] Failed to bind {}: {}", path, e);
+            return None;
+        }
+    };
+    eprintln!("[IPC] Listening on {}", path);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sender = sender.clone();
+                    thread::spawn(move || handle_client(stream, sender));
+                }
+                Err(e) => eprintln!("[IPC] Accept error: {}", e),
+            }
+        }
+    });
+    Some(path)
+}
+
+pub fn stop(path: &str) {
+    if let Err(e) = std::fs::remove_file(path) {
+        eprintln!("[IPC] Failed to remove {}: {}", path, e);
+    }
+}
+
+fn handle_client(stream: UnixStream, sender: glib::Sender<IpcCall>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Subscribe { events }) => {
+                add_subscriber(writer, events);
+                return;
+            }
+            Ok(request) => {
+                let (reply, result) = mpsc::channel();
+                if sender.send(IpcCall { request, reply }).is_err() {
+                    break;
+                }
+                result
+                    .recv()
+                    .unwrap_or_else(|_| Response::error("panel did not answer"))
+            }
+            Err(e) => Response::error(&format!("invalid request: {}", e)),
+        };
+
+        let json = serde_json::to_string(&response).unwrap_or_default();
+        if writeln!(writer, "{}", json).is_err() {
+            break;
+        }
+    }
+}
+
+pub fn attach(receiver: glib::Receiver<IpcCall>, panels: Rc<PanelManager>, config_path: String) {
+    receiver.attach(None, move |call| {
+        let response = match call.request {
+            Request::ToggleLauncher => {
+                panels.toggle_popup("launcher");
+                Response::ok(None)
+            }
+            Request::ToggleWifi => {
+                panels.toggle_popup("wifi");
+                Response::ok(None)
+            }
+            Request::ToggleAudio => {
+                panels.toggle_popup("audio");
+                Response::ok(None)
+            }
+            Request::ReloadConfig => {
+                panels.reload_config(&Config::load(&config_path));
+                Response::ok(None)
+            }
+            Request::ListWindows => {
+                let windows = panels.list_windows();
+                Response::ok(serde_json::to_value(windows).ok())
+            }
+            Request::ActivateWindow { id } => {
+                if panels.activate_window(id) {
+                    Response::ok(None)
+                } else {
+                    Response::error(&format!("no window with id {}", id))
+                }
+            }
+            Request::ShowDesktop => {
+                panels.toggle_desktop();
+                Response::ok(None)
+            }
+            Request::Thumbnail { id, path } => {
+                match panels.save_thumbnail(id, path, call.reply.clone()) {
+                    Ok(()) => return glib::ControlFlow::Continue,
+                    Err(e) => Response::error(&e),
+                }
+            }
+            Request::Subscribe { .. } => Response::error("subscribe is handled per connection"),
+        };
+        call.reply.send(response).ok();
+        glib::ControlFlow::Continue
+    });
+}
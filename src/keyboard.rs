@@ -0,0 +1,443 @@
+use lazy_static::lazy_static;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use xkbcommon::xkb;
+
+// One layout of the current keymap, in group order.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    // The layout code, upper-case, e.g. "US".
+    pub short: String,
+    // Full name as the keymap gives it, e.g. "English (US, intl., with dead keys)".
+    pub name: String,
+    // The xkb variant from the include string, e.g. "intl".
+    pub variant: Option<String>,
+}
+
+impl Layout {
+    // The include form, "us(intl)", used to tell variants of a layout apart.
+    pub fn code(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}({})", self.short.to_lowercase(), variant),
+            None => self.short.to_lowercase(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Locks {
+    pub caps: bool,
+    pub num: bool,
+    pub scroll: bool,
+}
+
+impl Locks {
+    // Read from the LEDs rather than the locked modifiers: keymaps differ in
+    // which modifier Num Lock and Scroll Lock use, but name their LEDs alike.
+    pub fn from_state(state: &xkb::State) -> Self {
+        Locks {
+            caps: state.led_name_is_active(xkb::LED_NAME_CAPS),
+            num: state.led_name_is_active(xkb::LED_NAME_NUM),
+            scroll: state.led_name_is_active(xkb::LED_NAME_SCROLL),
+        }
+    }
+}
+
+// What the keyboard module shows. Updated from wl_keyboard's keymap and
+// modifiers events; the UI is told with UiEvent::Keyboard.
+#[derive(Debug, Clone, Default)]
+pub struct Keyboard {
+    pub layouts: Vec<Layout>,
+    pub current: usize,
+    pub locks: Locks,
+}
+
+impl Keyboard {
+    pub fn current(&self) -> Option<&Layout> {
+        self.layouts.get(self.current)
+    }
+}
+
+// A layout named in the keymap's symbols, e.g. "us(intl)" or "gb:2".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeLayout {
+    pub layout: String,
+    pub variant: Option<String>,
+}
+
+// Symbols files the rules add for models and options rather than layouts.
+const NOT_LAYOUTS: &[&str] = &[
+    "pc",
+    "evdev",
+    "inet",
+    "base",
+    "aliases",
+    "empty",
+    "complete",
+    "group",
+    "level2",
+    "level3",
+    "level5",
+    "lv3",
+    "lv5",
+    "compose",
+    "caps",
+    "capslock",
+    "ctrl",
+    "altwin",
+    "keypad",
+    "kpdl",
+    "nbsp",
+    "numpad",
+    "shift",
+    "srvr_ctrl",
+    "terminate",
+    "eurosign",
+    "rupeesign",
+    "typo",
+    "misc",
+    "mod_led",
+    "japan",
+];
+
+// The rules string the symbols were built from. Compiled keymaps, as
+// compositors send them, carry it as the section name:
+// `xkb_symbols "pc+us+inet(evdev)" {`; keymaps written by hand include it:
+// `xkb_symbols { include "pc+us+inet(evdev)" };`.
+pub fn symbols_include(keymap: &str) -> Option<&str> {
+    let section = &keymap[keymap.find("xkb_symbols")? + "xkb_symbols".len()..];
+    let section = section.trim_start();
+    let quoted = match section.strip_prefix('"') {
+        Some(name) => name,
+        None => {
+            let body = &section[..section.find('}').unwrap_or(section.len())];
+            let include = &body[body.find("include")? + "include".len()..];
+            include.trim_start().strip_prefix('"')?
+        }
+    };
+    let include = &quoted[..quoted.find('"')?];
+    // Unnamed keymaps and empty includes say nothing about the layouts.
+    if include.is_empty() || include.starts_with('(') {
+        None
+    } else {
+        Some(include)
+    }
+}
+
+// Splits a symbols rules string into layouts, indexed by group. A part without
+// `:N` belongs to the first group, so "pc+us(intl)+gb:2+inet(evdev)" gives
+// us(intl) and then gb. Groups nothing was found for stay None.
+pub fn parse_include(include: &str) -> Vec<Option<IncludeLayout>> {
+    let mut groups: Vec<Option<IncludeLayout>> = Vec::new();
+    for part in include.split(['+', '|']) {
+        let (part, group) = match part.rsplit_once(':') {
+            Some((part, index)) => match index.trim().parse::<usize>() {
+                Ok(index) if index > 0 => (part, index),
+                _ => continue,
+            },
+            None => (part, 1),
+        };
+        let (layout, variant) = match part.split_once('(') {
+            Some((layout, rest)) => {
+                let variant = rest.split(')').next().unwrap_or("").trim();
+                (
+                    layout.trim(),
+                    (!variant.is_empty()).then(|| variant.to_string()),
+                )
+            }
+            None => (part.trim(), None),
+        };
+        if layout.is_empty() || NOT_LAYOUTS.contains(&layout) {
+            continue;
+        }
+        if groups.len() < group {
+            groups.resize(group, None);
+        }
+        // Later parts override earlier ones, as in xkbcomp.
+        groups[group - 1] = Some(IncludeLayout {
+            layout: layout.to_string(),
+            variant,
+        });
+    }
+    groups
+}
+
+// A layout or variant from the xkeyboard-config registry.
+#[derive(Debug, Clone)]
+pub struct RegistryEntry {
+    pub layout: String,
+    pub variant: Option<String>,
+    pub description: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    entries: Vec<RegistryEntry>,
+}
+
+const REGISTRY_FILES: &[&str] = &["rules/evdev.xml", "rules/evdev.extras.xml"];
+
+lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::load();
+}
+
+impl Registry {
+    fn load() -> Self {
+        let roots = std::env::var("XKB_CONFIG_ROOT").into_iter().chain(
+            ["/usr/share/X11/xkb", "/usr/local/share/X11/xkb"]
+                .iter()
+                .map(|root| root.to_string()),
+        );
+        for root in roots {
+            let mut entries = Vec::new();
+            for file in REGISTRY_FILES {
+                let path = std::path::Path::new(&root).join(file);
+                if let Ok(xml) = std::fs::read_to_string(&path) {
+                    entries.extend(Registry::parse(&xml));
+                }
+            }
+            if !entries.is_empty() {
+                eprintln!(
+                    "[Keyboard] Loaded {} layouts and variants from {}",
+                    entries.len(),
+                    root
+                );
+                return Registry { entries };
+            }
+        }
+        eprintln!("[Keyboard] No xkeyboard-config registry found");
+        Registry::default()
+    }
+
+    // Reads the `<layout>` and `<variant>` config items; models and options
+    // use the same tags and are skipped by their parents.
+    fn parse(xml: &str) -> Vec<RegistryEntry> {
+        let mut reader = Reader::from_str(xml);
+        let mut path: Vec<String> = Vec::new();
+        let mut text = String::new();
+        let (mut name, mut description) = (String::new(), String::new());
+        let mut layout = String::new();
+        let mut entries = Vec::new();
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(tag)) => {
+                    path.push(String::from_utf8_lossy(tag.name().as_ref()).into_owned());
+                    text.clear();
+                }
+                Ok(Event::Text(t)) => text.push_str(&t.decode().unwrap_or_default()),
+                // Entities arrive separately; keep them escaped until the tag ends.
+                Ok(Event::GeneralRef(r)) => {
+                    text.push('&');
+                    text.push_str(&r.decode().unwrap_or_default());
+                    text.push(';');
+                }
+                Ok(Event::End(_)) => {
+                    let tag = path.pop().unwrap_or_default();
+                    let in_item = path.last().map(String::as_str) == Some("configItem");
+                    let value = || {
+                        quick_xml::escape::unescape(text.trim())
+                            .map(|v| v.into_owned())
+                            .unwrap_or_else(|_| text.trim().to_string())
+                    };
+                    match tag.as_str() {
+                        "name" if in_item => name = value(),
+                        "description" if in_item => description = value(),
+                        "configItem" => {
+                            match path.last().map(String::as_str) {
+                                Some("layout") => {
+                                    layout = name.clone();
+                                    entries.push(RegistryEntry {
+                                        layout: layout.clone(),
+                                        variant: None,
+                                        description: description.clone(),
+                                    });
+                                }
+                                Some("variant") => entries.push(RegistryEntry {
+                                    layout: layout.clone(),
+                                    variant: Some(name.clone()),
+                                    description: description.clone(),
+                                }),
+                                _ => {}
+                            }
+                            name.clear();
+                            description.clear();
+                        }
+                        _ => {}
+                    }
+                    text.clear();
+                }
+                Ok(Event::Eof) => break,
+                Err(e) => {
+                    eprintln!("[Keyboard] Bad registry XML: {}", e);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        entries
+    }
+
+    // Keymaps name their groups with the registry's descriptions.
+    pub fn by_description(&self, description: &str) -> Option<&RegistryEntry> {
+        self.entries.iter().find(|e| e.description == description)
+    }
+
+    pub fn description(&self, layout: &str, variant: Option<&str>) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|e| e.layout == layout && e.variant.as_deref() == variant)
+            .map(|e| e.description.as_str())
+    }
+}
+
+// Names the groups of a freshly compiled keymap. The rules string is the most
+// direct source of layout and variant; the group's name, looked up in the
+// registry, covers keymaps that lost it. Either way the short label is the
+// layout code, so "English (US, intl.)" and "English (UK)" show up as US and GB.
+pub fn layouts(keymap: &xkb::Keymap, keymap_string: &str) -> Vec<Layout> {
+    let include = symbols_include(keymap_string);
+    eprintln!("[Keyboard] Symbols: {:?}", include);
+    let groups = include.map(parse_include).unwrap_or_default();
+
+    (0..keymap.num_layouts())
+        .map(|i| {
+            let name = keymap.layout_get_name(i).to_string();
+            let (layout, variant) = match groups.get(i as usize).cloned().flatten() {
+                Some(found) => (found.layout, found.variant),
+                None => match REGISTRY.by_description(&name) {
+                    Some(entry) => (entry.layout.clone(), entry.variant.clone()),
+                    None => {
+                        // Nothing to go by but the name's first letters.
+                        let short: String = name.chars().take(2).collect();
+                        (short.to_lowercase(), None)
+                    }
+                },
+            };
+            let name = if name.is_empty() {
+                REGISTRY
+                    .description(&layout, variant.as_deref())
+                    .map(str::to_string)
+                    .unwrap_or_default()
+            } else {
+                name
+            };
+            Layout {
+                short: layout.to_uppercase(),
+                name,
+                variant,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(layout: &str, variant: Option<&str>) -> Option<IncludeLayout> {
+        Some(IncludeLayout {
+            layout: layout.to_string(),
+            variant: variant.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn single_layout() {
+        assert_eq!(parse_include("pc+us+inet(evdev)"), vec![layout("us", None)]);
+    }
+
+    #[test]
+    fn multi_layout_with_group_indices() {
+        assert_eq!(
+            parse_include("pc+us+ru:2+de:3+inet(evdev)"),
+            vec![layout("us", None), layout("ru", None), layout("de", None)]
+        );
+    }
+
+    #[test]
+    fn variants() {
+        assert_eq!(
+            parse_include("pc+us(intl)+gb:2+inet(evdev)"),
+            vec![layout("us", Some("intl")), layout("gb", None)]
+        );
+        assert_eq!(
+            parse_include("pc+de(nodeadkeys)+ru(winkeys):2+inet(evdev)"),
+            vec![
+                layout("de", Some("nodeadkeys")),
+                layout("ru", Some("winkeys"))
+            ]
+        );
+    }
+
+    #[test]
+    fn options_are_not_layouts() {
+        assert_eq!(
+            parse_include(
+                "pc+us+ua:2+inet(evdev)+group(alt_shift_toggle)+level3(ralt_switch)+ctrl(nocaps)"
+            ),
+            vec![layout("us", None), layout("ua", None)]
+        );
+    }
+
+    #[test]
+    fn missing_groups_stay_empty() {
+        assert_eq!(
+            parse_include("pc+us+fr:3"),
+            vec![layout("us", None), None, layout("fr", None)]
+        );
+        assert_eq!(parse_include("pc+inet(evdev)"), vec![]);
+        assert_eq!(parse_include(""), vec![]);
+    }
+
+    #[test]
+    fn symbols_from_compiled_keymap() {
+        let keymap = "xkb_keymap {\n\txkb_keycodes \"evdev+aliases(qwerty)\" {\n\t};\n\
+                      \txkb_symbols \"pc+us+ru:2+inet(evdev)\" {\n\t\tname[Group1]=\"English (US)\";\n\t};\n};";
+        assert_eq!(symbols_include(keymap), Some("pc+us+ru:2+inet(evdev)"));
+    }
+
+    #[test]
+    fn symbols_from_include() {
+        let keymap = "xkb_keymap {\n\txkb_symbols { include \"pc+gb+inet(evdev)\" };\n};";
+        assert_eq!(symbols_include(keymap), Some("pc+gb+inet(evdev)"));
+        assert_eq!(symbols_include("xkb_symbols \"(unnamed)\" {};"), None);
+        assert_eq!(symbols_include("xkb_keymap {};"), None);
+    }
+
+    #[test]
+    fn registry_entries() {
+        let xml = r#"<xkbConfigRegistry>
+  <modelList><model><configItem><name>pc105</name><description>Generic 105-key PC</description></configItem></model></modelList>
+  <layoutList>
+    <layout>
+      <configItem><name>us</name><shortDescription>en</shortDescription><description>English (US)</description></configItem>
+      <variantList>
+        <variant><configItem><name>intl</name><description>English (US, intl., with dead keys)</description></configItem></variant>
+      </variantList>
+    </layout>
+    <layout>
+      <configItem><name>cz</name><description>Czech</description></configItem>
+      <variantList>
+        <variant><configItem><name>bksl</name><description>Czech (with &lt;\|&gt; key)</description></configItem></variant>
+      </variantList>
+    </layout>
+  </layoutList>
+</xkbConfigRegistry>"#;
+        let registry = Registry {
+            entries: Registry::parse(xml),
+        };
+        assert_eq!(registry.entries.len(), 4);
+        let intl = registry
+            .by_description("English (US, intl., with dead keys)")
+            .unwrap();
+        assert_eq!(intl.layout, "us");
+        assert_eq!(intl.variant.as_deref(), Some("intl"));
+        assert_eq!(registry.description("us", None), Some("English (US)"));
+        assert_eq!(
+            registry.description("cz", Some("bksl")),
+            Some("Czech (with <\\|> key)")
+        );
+        assert!(registry.by_description("Generic 105-key PC").is_none());
+    }
+}
@@ -1,86 +1,17 @@
-use crate::utils::fix_icon_name;
-use gdk::EventButton;
-use gio::AppInfo;
+use crate::config::PanelEdge;
 use gtk::prelude::*;
 use gtk::{
     Box, Button, FlowBox, IconSize, Image, Label, Menu, MenuItem, Orientation, ScrolledWindow,
     SearchEntry, Separator, Window, WindowType,
 };
 use gtk_layer_shell::LayerShell;
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::fs;
-use std::io::Write;
-use std::path::Path;
+use std::cell::{Cell, RefCell};
 use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-
-fn toggle_pin_json(desktop_file: &str, pin: bool) {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    let path_str = format!("{}/.config/labar/pinned.json", home);
-    let path = Path::new(&path_str);
-
-    if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
-    }
-
-    let mut apps: Vec<PinnedApp> = Vec::new();
-    if path.exists() {
-        if let Ok(content) = fs::read_to_string(path) {
-            apps = serde_json::from_str(&content).unwrap_or_default();
-        }
-    }
-
-    if pin {
-        if !apps.iter().any(|a| a.desktop_file == desktop_file) {
-            apps.push(PinnedApp {
-                desktop_file: desktop_file.to_string(),
-            });
-        }
-    } else {
-        apps.retain(|a| a.desktop_file != desktop_file);
-    }
-
-    if let Ok(mut file) = fs::File::create(path) {
-        let json = serde_json::to_string_pretty(&apps).unwrap_or_default();
-        let _ = file.write_all(json.as_bytes());
-    }
-}
-
-fn pin_to_taskbar(desktop_file: &str) {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    let path = format!("{}/.config/taskbar_pinned.txt", home);
-
-    let mut lines = Vec::new();
-    if let Ok(content) = fs::read_to_string(&path) {
-        for line in content.lines() {
-            if !line.trim().is_empty() {
-                lines.push(line.to_string());
-            }
-        }
-    }
-
-    if !lines.iter().any(|l| l == desktop_file) {
-        lines.push(desktop_file.to_string());
-    }
-
-    if let Ok(mut file) = fs::File::create(&path) {
-        for l in lines {
-            writeln!(file, "{}", l).ok();
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct PinnedApp {
-    desktop_file: String,
-}
+use std::rc::Rc;
 
 #[derive(Clone)]
 struct AppData {
     name: String,
-    icon: String,
     description: String,
     desktop_file: String,
     app_info: gio::AppInfo,
@@ -90,16 +21,15 @@ struct AppData {
 pub struct AppLauncher {
     window: Window,
     backdrop: Window,
-    main_box: Box,
     search_entry: SearchEntry,
     apps_grid: FlowBox,
     pinned_grid: FlowBox,
     pinned_separator: Separator,
-    scroll: ScrolledWindow,
     pinned_label: Label,
-    all_apps: Arc<Mutex<Vec<AppData>>>,
-    pinned_apps: Arc<Mutex<Vec<String>>>,
-    trigger_button: Arc<Mutex<Option<gtk::Widget>>>,
+    all_apps: Rc<RefCell<Vec<AppData>>>,
+    pinned_apps: Rc<RefCell<Vec<String>>>,
+    trigger_button: RefCell<Option<gtk::Widget>>,
+    panel_edge: Cell<PanelEdge>,
 }
 
 impl AppLauncher {
@@ -114,14 +44,9 @@ impl AppLauncher {
         backdrop.set_decorated(false);
         backdrop.set_widget_name("backdrop-window");
 
-        let backdrop_css = gtk::CssProvider::new();
-        backdrop_css
-            .load_from_data(b"#backdrop-window { background: transparent; }")
-            .ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &backdrop_css,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        crate::style::add_builtin(
+            "backdrop",
+            b"#backdrop-window { background: transparent; }",
         );
 
         let window = Window::new(WindowType::Toplevel);
@@ -163,6 +88,7 @@ impl AppLauncher {
         pinned_grid.set_margin_start(20);
         pinned_grid.set_margin_end(20);
         pinned_grid.set_margin_top(10);
+        crate::modules::pinned::set_drop_target(&pinned_grid, None);
         main_box.pack_start(&pinned_grid, false, false, 0);
 
         let pinned_separator = Separator::new(Orientation::Horizontal);
@@ -212,27 +138,20 @@ impl AppLauncher {
         }
         main_box.pack_start(&power_box, false, false, 0);
 
-        let provider = gtk::CssProvider::new();
-        provider.load_from_data(include_bytes!("launcher.css")).ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
+        crate::style::add_builtin("launcher.css", include_bytes!("launcher.css"));
 
         let instance = AppLauncher {
             window,
             backdrop,
-            main_box,
             search_entry,
             apps_grid,
             pinned_grid,
             pinned_separator,
-            scroll,
             pinned_label,
-            all_apps: Arc::new(Mutex::new(Vec::new())),
-            pinned_apps: Arc::new(Mutex::new(Vec::new())),
-            trigger_button: Arc::new(Mutex::new(None)),
+            all_apps: Rc::new(RefCell::new(Vec::new())),
+            pinned_apps: Rc::new(RefCell::new(Vec::new())),
+            trigger_button: RefCell::new(None),
+            panel_edge: Cell::new(PanelEdge::Bottom),
         };
 
         let win_for_backdrop = instance.window.clone();
@@ -280,19 +199,11 @@ impl AppLauncher {
     }
 
     fn load_pinned_list() -> Vec<String> {
-        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        let mut pinned_set: HashSet<String> = HashSet::new();
-
-        let json_path = format!("{}/.config/labar/pinned.json", home);
-        if let Ok(content) = fs::read_to_string(&json_path) {
-            if let Ok(apps) = serde_json::from_str::<Vec<PinnedApp>>(&content) {
-                for app in apps {
-                    pinned_set.insert(app.desktop_file);
-                }
-            }
-        }
-
-        pinned_set.into_iter().collect()
+        crate::pins::list()
+            .into_iter()
+            .map(|app| app.desktop_id)
+            .filter(|id| !id.is_empty())
+            .collect()
     }
 
     fn load_applications(&self) {
@@ -302,25 +213,14 @@ impl AppLauncher {
         for app in gio::AppInfo::all() {
             if app.should_show() {
                 let name = app.name().to_string();
-                let exec = app.executable().to_string_lossy().to_string();
                 let description = app.description().map(|s| s.to_string()).unwrap_or_default();
 
                 let desktop_file = app.id().map(|s| s.to_string()).unwrap_or_default();
 
-                let icon_str = if let Some(icon) = app.icon() {
-                    use gtk::prelude::IconExt;
-                    IconExt::to_string(&icon)
-                        .map(|s| s.to_string())
-                        .unwrap_or_else(|| "application-x-executable".to_string())
-                } else {
-                    "application-x-executable".to_string()
-                };
-
                 let is_pinned = pinned_list.contains(&desktop_file);
 
                 apps.push(AppData {
                     name,
-                    icon: fix_icon_name(&icon_str),
                     description,
                     desktop_file,
                     app_info: app,
@@ -329,10 +229,10 @@ impl AppLauncher {
             }
         }
 
-        apps.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+        apps.sort_by_key(|a| a.name.to_lowercase());
 
-        *self.all_apps.lock().unwrap() = apps;
-        *self.pinned_apps.lock().unwrap() = pinned_list;
+        *self.all_apps.borrow_mut() = apps;
+        *self.pinned_apps.borrow_mut() = pinned_list;
 
         Self::refresh_ui(
             &self.apps_grid,
@@ -346,21 +246,22 @@ impl AppLauncher {
         );
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn refresh_ui(
         apps_grid: &FlowBox,
         pinned_grid: &FlowBox,
         pinned_label: &Label,
         pinned_separator: &Separator,
-        all_apps: &Arc<Mutex<Vec<AppData>>>,
-        pinned_apps: &Arc<Mutex<Vec<String>>>,
+        all_apps: &Rc<RefCell<Vec<AppData>>>,
+        pinned_apps: &Rc<RefCell<Vec<String>>>,
         window: &Window,
         filter: Option<&str>,
     ) {
         apps_grid.foreach(|w| apps_grid.remove(w));
         pinned_grid.foreach(|w| pinned_grid.remove(w));
 
-        let apps = all_apps.lock().unwrap();
-        let pinned = pinned_apps.lock().unwrap().clone();
+        let apps = all_apps.borrow();
+        let pinned = pinned_apps.borrow().clone();
 
         let is_searching = filter.map(|f| !f.is_empty()).unwrap_or(false);
         let filter_lower = filter.unwrap_or("").to_lowercase();
@@ -375,14 +276,7 @@ impl AppLauncher {
 
             for desktop_file in &pinned {
                 if let Some(app) = apps.iter().find(|a| a.desktop_file == *desktop_file) {
-                    let btn = Self::create_app_button(
-                        app,
-                        window,
-                        pinned_grid,
-                        all_apps,
-                        pinned_apps,
-                        false,
-                    );
+                    let btn = Self::create_app_button(app, window, all_apps, pinned_apps, false);
                     pinned_grid.add(&btn);
                 }
             }
@@ -399,14 +293,7 @@ impl AppLauncher {
                     || app.description.to_lowercase().contains(&filter_lower);
 
                 if matches {
-                    let btn = Self::create_app_button(
-                        app,
-                        window,
-                        apps_grid,
-                        all_apps,
-                        pinned_apps,
-                        true,
-                    );
+                    let btn = Self::create_app_button(app, window, all_apps, pinned_apps, true);
                     apps_grid.add(&btn);
                     count += 1;
                 }
@@ -435,9 +322,8 @@ impl AppLauncher {
     fn create_app_button(
         app: &AppData,
         window: &Window,
-        grid: &FlowBox,
-        all_apps: &Arc<Mutex<Vec<AppData>>>,
-        pinned_apps: &Arc<Mutex<Vec<String>>>,
+        all_apps: &Rc<RefCell<Vec<AppData>>>,
+        pinned_apps: &Rc<RefCell<Vec<String>>>,
         list_mode: bool,
     ) -> gtk::EventBox {
         let event_box = gtk::EventBox::new();
@@ -501,26 +387,39 @@ impl AppLauncher {
             btn_box.pack_start(&label, false, false, 0);
         }
 
+        let icon = app
+            .app_info
+            .icon()
+            .unwrap_or_else(|| gio::ThemedIcon::new("application-x-executable").upcast());
+        crate::modules::pinned::set_drag_source(&event_box, &app.desktop_file, &icon);
+        if !list_mode {
+            crate::modules::pinned::set_drop_target(&event_box, Some(app.desktop_file.clone()));
+        }
+
+        // Launch on release so a press can still start a drag.
         let app_info = app.app_info.clone();
         let win_weak = window.downgrade();
+        event_box.connect_button_release_event(move |_, event| {
+            if event.button() == 1 {
+                let _ = app_info.launch(&[], None::<&gio::AppLaunchContext>);
+                if let Some(win) = win_weak.upgrade() {
+                    win.hide();
+                }
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        });
 
         event_box.connect_button_press_event({
             let desktop_file = app.desktop_file.clone();
             let is_pinned = app.pinned;
-            let grid_clone = grid.clone();
             let all_apps_clone = all_apps.clone();
             let pinned_apps_clone = pinned_apps.clone();
-            let event_box_clone = event_box.clone();
 
             move |_, event| {
-                if event.button() == 1 {
-                    let _ = app_info.launch(&[], None::<&gio::AppLaunchContext>);
-                    if let Some(win) = win_weak.upgrade() {
-                        win.hide();
-                    }
-                    return glib::Propagation::Stop;
-                } else if event.button() == 3 {
+                if event.button() == 3 {
                     let menu = Menu::new();
+                    crate::desktop::append_actions(&menu, &desktop_file);
 
                     let pin_label = if is_pinned {
                         &crate::locales::LOCALE.unpin
@@ -534,16 +433,15 @@ impl AppLauncher {
 
                     pin_item.connect_activate(move |_| {
                         if is_pinned {
-                            toggle_pin_json(&df, false);
-                            pinned_apps_c.lock().unwrap().retain(|p| p != &df);
+                            crate::pins::unpin(&df);
+                            pinned_apps_c.borrow_mut().retain(|p| p != &df);
                         } else {
-                            toggle_pin_json(&df, true);
-                            pinned_apps_c.lock().unwrap().push(df.clone());
+                            crate::pins::pin_desktop(&df);
+                            pinned_apps_c.borrow_mut().push(df.clone());
                         }
 
                         if let Some(app) = all_apps_c
-                            .lock()
-                            .unwrap()
+                            .borrow_mut()
                             .iter_mut()
                             .find(|a| a.desktop_file == df)
                         {
@@ -552,13 +450,6 @@ impl AppLauncher {
                     });
                     menu.append(&pin_item);
 
-                    let taskbar_item = MenuItem::with_label(&crate::locales::LOCALE.pin_to_taskbar);
-                    let df2 = desktop_file.clone();
-                    taskbar_item.connect_activate(move |_| {
-                        pin_to_taskbar(&df2);
-                    });
-                    menu.append(&taskbar_item);
-
                     menu.show_all();
                     menu.popup_at_pointer(Some(event));
                     return glib::Propagation::Stop;
@@ -570,13 +461,8 @@ impl AppLauncher {
         event_box
     }
 
-    pub fn get_window(&self) -> &Window {
-        &self.window
-    }
-
     pub fn set_trigger_button<W: IsA<gtk::Widget>>(&self, button: &W) {
-        let mut btn = self.trigger_button.lock().unwrap();
-        *btn = Some(button.clone().upcast());
+        *self.trigger_button.borrow_mut() = Some(button.clone().upcast());
     }
 
     pub fn toggle(&self) {
@@ -584,24 +470,8 @@ impl AppLauncher {
             self.window.hide();
             self.backdrop.hide();
         } else {
-            *self.pinned_apps.lock().unwrap() = Self::load_pinned_list();
-
-            let pinned = self.pinned_apps.lock().unwrap().clone();
-            for app in self.all_apps.lock().unwrap().iter_mut() {
-                app.pinned = pinned.contains(&app.desktop_file);
-            }
-
             self.search_entry.set_text("");
-            Self::refresh_ui(
-                &self.apps_grid,
-                &self.pinned_grid,
-                &self.pinned_label,
-                &self.pinned_separator,
-                &self.all_apps,
-                &self.pinned_apps,
-                &self.window,
-                None,
-            );
+            self.reload_pinned();
 
             self.backdrop.show_all();
             self.window.show_all();
@@ -611,10 +481,48 @@ impl AppLauncher {
         }
     }
 
+    pub fn reload_pinned(&self) {
+        *self.pinned_apps.borrow_mut() = Self::load_pinned_list();
+
+        let pinned = self.pinned_apps.borrow().clone();
+        for app in self.all_apps.borrow_mut().iter_mut() {
+            app.pinned = pinned.contains(&app.desktop_file);
+        }
+
+        let text = self.search_entry.text().to_string().to_lowercase();
+        Self::refresh_ui(
+            &self.apps_grid,
+            &self.pinned_grid,
+            &self.pinned_label,
+            &self.pinned_separator,
+            &self.all_apps,
+            &self.pinned_apps,
+            &self.window,
+            Some(&text),
+        );
+    }
+
     fn position_window(&self) {
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Bottom, true);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Left, false);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Right, false);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Top, false);
+        let trigger = self.trigger_button.borrow().clone();
+        crate::utils::anchor_popup(
+            &self.window,
+            self.panel_edge.get(),
+            false,
+            trigger.as_ref(),
+        );
+    }
+}
+
+impl crate::modules::PanelPopup for AppLauncher {
+    fn toggle(&self) {
+        AppLauncher::toggle(self);
+    }
+
+    fn set_trigger(&self, widget: &gtk::Widget) {
+        self.set_trigger_button(widget);
+    }
+
+    fn set_edge(&self, edge: PanelEdge) {
+        self.panel_edge.set(edge);
     }
 }
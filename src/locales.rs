@@ -1,10 +1,9 @@
 use lazy_static::lazy_static;
 use serde::Deserialize;
-use std::collections::HashMap;
 use std::fs;
-use std::sync::Mutex;
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Localization {
     pub search_placeholder: String,
     pub pinned_label: String,
@@ -33,6 +32,18 @@ pub struct Localization {
     pub input_device: String,
     pub apps_label: String,
     pub no_audio_apps: String,
+    pub minimize: String,
+    pub restore: String,
+    pub maximize: String,
+    pub unmaximize: String,
+    pub fullscreen: String,
+    pub leave_fullscreen: String,
+    pub new_window: String,
+    pub taskbar_unavailable: String,
+    pub taskbar_unavailable_tooltip: String,
+    pub caps_lock: String,
+    pub num_lock: String,
+    pub scroll_lock: String,
 }
 
 impl Default for Localization {
@@ -65,6 +76,19 @@ impl Default for Localization {
             input_device: "Input Device".to_string(),
             apps_label: "Applications".to_string(),
             no_audio_apps: "No apps using audio".to_string(),
+            minimize: "Minimize".to_string(),
+            restore: "Restore".to_string(),
+            maximize: "Maximize".to_string(),
+            unmaximize: "Unmaximize".to_string(),
+            fullscreen: "Fullscreen".to_string(),
+            leave_fullscreen: "Leave fullscreen".to_string(),
+            new_window: "New window".to_string(),
+            taskbar_unavailable: "No window list".to_string(),
+            taskbar_unavailable_tooltip: "The compositor offers no foreign toplevel protocol"
+                .to_string(),
+            caps_lock: "Caps Lock".to_string(),
+            num_lock: "Num Lock".to_string(),
+            scroll_lock: "Scroll Lock".to_string(),
         }
     }
 }
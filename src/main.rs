@@ -1,55 +1,116 @@
-use gtk::prelude::*;
 use std::rc::Rc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
 
 mod audio;
+mod cli;
+mod config;
+mod desktop;
+mod ext_toplevels;
+mod ipc;
+mod keyboard;
 mod launcher;
 mod locales;
+mod modules;
 mod panel;
+mod pins;
+mod screencopy;
+mod style;
+mod supervisor;
+mod unity;
 mod utils;
 mod wayland;
 mod wifi;
+mod workspaces;
 
 use audio::AudioMixerPopup;
 use launcher::AppLauncher;
-use panel::Labar;
+use panel::PanelManager;
 use wifi::WiFiPopup;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = match cli::parse(&args) {
+        Ok(cli::Command::Run { config }) => config.unwrap_or_else(config::Config::default_path),
+        Ok(cli::Command::Msg { request, json }) => {
+            std::process::exit(cli::run_msg(&request, json));
+        }
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("labar: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let config = config::Config::load(&config_path);
+    if config.reconnect && !supervisor::is_supervised() {
+        std::process::exit(supervisor::run());
+    }
+
     gtk::init().expect("Failed to initialize GTK");
+    desktop::set_overrides(&config.desktop_entries);
 
     let launcher = Rc::new(AppLauncher::new());
     let wifi = Rc::new(WiFiPopup::new());
     let audio = Rc::new(AudioMixerPopup::new());
 
-    let panel = Labar::new(launcher.clone(), wifi.clone(), audio.clone());
-
-    let signal_flag = Arc::new(AtomicBool::new(false));
-    let signal_flag_clone = signal_flag.clone();
-
-    signal_hook::flag::register(signal_hook::consts::SIGUSR1, signal_flag_clone)
-        .expect("Failed to register SIGUSR1 handler");
+    let panels = PanelManager::new(&config, launcher.clone(), wifi.clone(), audio.clone());
 
+    // Kept for existing `pkill -USR1 labar` keybindings; new setups should use the socket.
     let launcher_for_signal = launcher.clone();
-    glib::timeout_add_local(std::time::Duration::from_millis(50), move || {
-        if signal_flag.swap(false, Ordering::Relaxed) {
-            launcher_for_signal.toggle();
-        }
+    glib::unix_signal_add_local(signal_hook::consts::SIGUSR1, move || {
+        launcher_for_signal.toggle();
         glib::ControlFlow::Continue
     });
 
+    #[allow(deprecated)]
     let (ui_sender, ui_receiver) = glib::MainContext::channel(glib::Priority::default());
 
-    let wl_client = wayland::WaylandClient::new(ui_sender);
+    if let Err(e) = panels.connect_wayland(ui_sender) {
+        eprintln!("[Wayland] {}", e);
+        std::process::exit(1);
+    }
+    panels.set_ui_receiver(ui_receiver);
 
-    panel.set_wayland_windows(wl_client.windows.clone());
-    panel.set_wayland_seat(wl_client.seat.clone());
-    panel.set_wayland_conn(wl_client.conn.clone());
-    panel.set_keyboard_layout(wl_client.keyboard_layout.clone());
-    panel.set_ui_receiver(ui_receiver);
+    panels.start();
 
-    panel.show();
+    let panels_for_pins = panels.clone();
+    let launcher_for_pins = launcher.clone();
+    pins::connect_changed(move || {
+        panels_for_pins.refresh();
+        launcher_for_pins.reload_pinned();
+    });
+
+    let panels_for_unity = panels.clone();
+    unity::watch(move |desktop_id| {
+        panels_for_unity.launcher_entry_changed(desktop_id);
+    });
+
+    // Deprecated in glib 0.18, but the UI events use the same channel and
+    // moving both to async-channel is a change of its own.
+    #[allow(deprecated)]
+    let (ipc_sender, ipc_receiver) = glib::MainContext::channel(glib::Priority::default());
+    let socket = ipc::start(ipc_sender);
+    ipc::attach(ipc_receiver, panels.clone(), config_path);
+
+    let _user_style = style::UserStyle::watch(&style::UserStyle::default_path());
+
+    // Leave the main loop on the usual stop signals so the socket is removed.
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        glib::unix_signal_add_local(signal, || {
+            gtk::main_quit();
+            glib::ControlFlow::Break
+        });
+    }
 
     gtk::main();
+
+    if let Some(path) = socket {
+        ipc::stop(&path);
+    }
+
+    if panels.lost_connection() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,69 @@
+use gtk::prelude::*;
+use gtk::{Button, Label, Overlay, ProgressBar};
+
+use crate::unity::LauncherEntry;
+
+// The count badge, progress bar and urgency highlight drawn over an app
+// button, fed by the app's launcher entry.
+pub struct Badges {
+    pub overlay: Overlay,
+    pub button: Button,
+    count: Label,
+    progress: ProgressBar,
+}
+
+impl Badges {
+    pub fn new(button: &Button) -> Self {
+        let count = Label::new(None);
+        count.set_widget_name("task-badge");
+        count.set_halign(gtk::Align::End);
+        count.set_valign(gtk::Align::Start);
+        count.set_no_show_all(true);
+
+        let progress = ProgressBar::new();
+        progress.set_widget_name("task-progress");
+        progress.set_valign(gtk::Align::End);
+        progress.set_no_show_all(true);
+
+        let overlay = Overlay::new();
+        overlay.add(button);
+        overlay.add_overlay(&count);
+        overlay.add_overlay(&progress);
+        overlay.set_overlay_pass_through(&count, true);
+        overlay.set_overlay_pass_through(&progress, true);
+
+        Badges {
+            overlay,
+            button: button.clone(),
+            count,
+            progress,
+        }
+    }
+
+    // The app's own count wins over the number of grouped windows.
+    pub fn apply(&self, entry: &LauncherEntry, windows: usize) {
+        let count = entry.count.or((windows > 1).then_some(windows as i64));
+        match count {
+            Some(count) => {
+                self.count.set_text(&count.to_string());
+                self.count.show();
+            }
+            None => self.count.hide(),
+        }
+
+        match entry.progress {
+            Some(fraction) => {
+                self.progress.set_fraction(fraction);
+                self.progress.show();
+            }
+            None => self.progress.hide(),
+        }
+
+        let style = self.button.style_context();
+        if entry.urgent {
+            style.add_class("urgent");
+        } else {
+            style.remove_class("urgent");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::Local;
+use gtk::prelude::*;
+use gtk::Label;
+use serde::Deserialize;
+use std::rc::Rc;
+
+use super::{PanelContext, PanelModule};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub format: String,
+    // Used instead of `format` on left/right panels, where the text is stacked.
+    pub vertical_format: String,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig {
+            format: "%H:%M\n%d.%m.%Y".to_string(),
+            vertical_format: "%H\n%M\n\n%d\n%m".to_string(),
+        }
+    }
+}
+
+pub struct ClockModule {
+    label: Label,
+}
+
+impl ClockModule {
+    pub fn new(ctx: &Rc<PanelContext>, config: ClockConfig) -> Self {
+        let label = Label::new(Some("00:00"));
+        label.set_justify(gtk::Justification::Center);
+
+        let format = if ctx.config.edge.is_vertical() {
+            config.vertical_format
+        } else {
+            config.format
+        };
+
+        // The timer stops once the panel, and with it the label, is destroyed.
+        let label_weak = label.downgrade();
+        glib::timeout_add_seconds_local(1, move || {
+            let Some(label) = label_weak.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            let now = Local::now();
+            label.set_text(&now.format(&format).to_string());
+            glib::ControlFlow::Continue
+        });
+
+        ClockModule { label }
+    }
+}
+
+impl PanelModule for ClockModule {
+    fn name(&self) -> &'static str {
+        "clock"
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.label.clone().upcast()
+    }
+}
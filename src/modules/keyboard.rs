@@ -0,0 +1,243 @@
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::prelude::*;
+use gtk::{EventBox, Image, Label};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{PanelContext, PanelModule};
+use crate::keyboard::{Keyboard, Layout};
+use crate::wayland::UiEvent;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct KeyboardConfig {
+    // Text shown instead of a layout's short code, keyed by the code ("us")
+    // or by code and variant ("us(intl)") to tell variants apart.
+    pub labels: HashMap<String, String>,
+    // Icon name or image file shown instead of the text, keyed the same way.
+    pub icons: HashMap<String, String>,
+    // Lock indicators to show while the lock is on: "caps", "num", "scroll".
+    pub locks: Vec<String>,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        KeyboardConfig {
+            labels: HashMap::new(),
+            icons: HashMap::new(),
+            locks: vec!["caps".to_string(), "num".to_string()],
+        }
+    }
+}
+
+// The variant's entry wins over the plain layout's. Codes are matched
+// case-insensitively, since the panel shows them upper-case.
+fn lookup<'a>(map: &'a HashMap<String, String>, layout: &Layout) -> Option<&'a String> {
+    let code = layout.code();
+    let short = layout.short.to_lowercase();
+    [code, short].iter().find_map(|key| {
+        map.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    })
+}
+
+fn text(config: &KeyboardConfig, layout: &Layout) -> String {
+    lookup(&config.labels, layout)
+        .cloned()
+        .unwrap_or_else(|| layout.short.clone())
+}
+
+// Paths load an image file, anything else is an icon name.
+fn set_icon(image: &Image, icon: &str) -> bool {
+    if icon.contains('/') {
+        match Pixbuf::from_file_at_scale(icon, -1, 16, true) {
+            Ok(pixbuf) => image.set_from_pixbuf(Some(&pixbuf)),
+            Err(e) => {
+                eprintln!("[Keyboard] Cannot load {}: {}", icon, e);
+                return false;
+            }
+        }
+    } else {
+        image.set_from_icon_name(Some(icon), gtk::IconSize::Menu);
+    }
+    true
+}
+
+fn snapshot(ctx: &PanelContext) -> Keyboard {
+    ctx.keyboard
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|keyboard| keyboard.lock().unwrap().clone())
+        .unwrap_or_default()
+}
+
+// Lists every layout of the keymap with its full name, marking the current one.
+// Wayland gives clients no way to switch layouts, so the list is informational.
+fn popup(relative_to: &gtk::Widget, ctx: &PanelContext, config: &KeyboardConfig) {
+    let keyboard = snapshot(ctx);
+    if keyboard.layouts.is_empty() {
+        return;
+    }
+
+    let popover = gtk::Popover::new(Some(relative_to));
+    popover.set_widget_name("keyboard-layouts");
+    popover.set_constrain_to(gtk::PopoverConstraint::None);
+    popover.set_position(ctx.popover_position());
+
+    let list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+    for (index, layout) in keyboard.layouts.iter().enumerate() {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        if index == keyboard.current {
+            row.style_context().add_class("active");
+        }
+
+        let image = Image::new();
+        let has_icon = lookup(&config.icons, layout).is_some_and(|icon| set_icon(&image, icon));
+        if has_icon {
+            row.pack_start(&image, false, false, 0);
+        } else {
+            let short = Label::new(Some(&text(config, layout)));
+            short.set_width_chars(3);
+            row.pack_start(&short, false, false, 0);
+        }
+
+        let name = Label::new(Some(if layout.name.is_empty() {
+            &layout.short
+        } else {
+            &layout.name
+        }));
+        name.set_xalign(0.0);
+        row.pack_start(&name, true, true, 0);
+
+        let code = Label::new(Some(&layout.code()));
+        code.style_context().add_class("dim-label");
+        row.pack_end(&code, false, false, 0);
+
+        list.add(&row);
+    }
+
+    popover.add(&list);
+    list.show_all();
+    popover.popup();
+}
+
+pub struct KeyboardModule {
+    event_box: EventBox,
+    label: Label,
+    image: Image,
+    // Indicator and the lock it shows, in config order.
+    locks: Vec<(String, Label)>,
+    ctx: Rc<PanelContext>,
+    config: Rc<KeyboardConfig>,
+}
+
+impl KeyboardModule {
+    pub fn new(ctx: &Rc<PanelContext>, config: KeyboardConfig) -> Self {
+        // refresh() sets the real text once the keymap is known.
+        let label = Label::new(Some(".."));
+        label.set_widget_name("keyboard-layout");
+        label.set_no_show_all(true);
+        label.show();
+        if ctx.config.edge.is_vertical() {
+            label.set_max_width_chars(2);
+            label.set_ellipsize(pango::EllipsizeMode::End);
+        }
+
+        let image = Image::new();
+        image.set_widget_name("keyboard-flag");
+        image.set_no_show_all(true);
+
+        let container = gtk::Box::new(ctx.orientation(), 0);
+        container.pack_start(&image, false, false, 0);
+        container.pack_start(&label, false, false, 0);
+
+        let locale = &crate::locales::LOCALE;
+        let mut locks = Vec::new();
+        for lock in &config.locks {
+            let (symbol, tooltip) = match lock.as_str() {
+                "caps" => ("⇪", &locale.caps_lock),
+                "num" => ("⇭", &locale.num_lock),
+                "scroll" => ("⇳", &locale.scroll_lock),
+                other => {
+                    eprintln!("[Keyboard] Unknown lock indicator: {}", other);
+                    continue;
+                }
+            };
+            let indicator = Label::new(Some(symbol));
+            indicator.set_widget_name("keyboard-lock");
+            indicator.set_tooltip_text(Some(tooltip));
+            indicator.set_no_show_all(true);
+            container.pack_start(&indicator, false, false, 0);
+            locks.push((lock.clone(), indicator));
+        }
+
+        let event_box = EventBox::new();
+        event_box.add(&container);
+
+        let config = Rc::new(config);
+        let ctx_click = ctx.clone();
+        let config_click = config.clone();
+        event_box.connect_button_press_event(move |event_box, event| {
+            if event.button() == 1 {
+                popup(event_box.upcast_ref(), &ctx_click, &config_click);
+            }
+            glib::Propagation::Stop
+        });
+
+        let module = KeyboardModule {
+            event_box,
+            label,
+            image,
+            locks,
+            ctx: ctx.clone(),
+            config,
+        };
+        module.refresh();
+        module
+    }
+
+    fn refresh(&self) {
+        let keyboard = snapshot(&self.ctx);
+
+        if let Some(layout) = keyboard.current() {
+            let has_icon =
+                lookup(&self.config.icons, layout).is_some_and(|icon| set_icon(&self.image, icon));
+            self.image.set_visible(has_icon);
+            self.label.set_visible(!has_icon);
+            self.label.set_text(&text(&self.config, layout));
+            let tooltip = if layout.name.is_empty() {
+                None
+            } else {
+                Some(layout.name.as_str())
+            };
+            self.event_box.set_tooltip_text(tooltip);
+        }
+
+        for (lock, indicator) in &self.locks {
+            indicator.set_visible(match lock.as_str() {
+                "caps" => keyboard.locks.caps,
+                "num" => keyboard.locks.num,
+                _ => keyboard.locks.scroll,
+            });
+        }
+    }
+}
+
+impl PanelModule for KeyboardModule {
+    fn name(&self) -> &'static str {
+        "keyboard"
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.event_box.clone().upcast()
+    }
+
+    fn handle_event(&self, event: &UiEvent) {
+        if let UiEvent::Keyboard | UiEvent::Refresh = event {
+            self.refresh();
+        }
+    }
+}
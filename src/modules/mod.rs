@@ -0,0 +1,232 @@
+use gtk::prelude::*;
+use serde::de::DeserializeOwned;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use crate::audio::AudioMixerPopup;
+use crate::config::{Config, PanelEdge};
+use crate::keyboard::Keyboard;
+use crate::launcher::AppLauncher;
+use crate::screencopy::Capturer;
+use crate::wayland::{Capabilities, ToplevelId, UiEvent, WindowHandle};
+use crate::wifi::WiFiPopup;
+use crate::workspaces::Workspaces;
+
+mod badges;
+mod clock;
+mod keyboard;
+pub mod pinned;
+mod popup_button;
+pub mod show_desktop;
+mod taskbar;
+mod window_list;
+mod workspaces;
+
+pub type SharedWindows = Arc<Mutex<Option<Arc<Mutex<Vec<WindowHandle>>>>>>;
+pub type SharedSeat =
+    Arc<Mutex<Option<Arc<Mutex<Option<wayland_client::protocol::wl_seat::WlSeat>>>>>>;
+pub type SharedConn = Arc<Mutex<Option<wayland_client::Connection>>>;
+pub type SharedCapturer = Arc<Mutex<Option<Arc<Capturer>>>>;
+pub type SharedCapabilities = Arc<Mutex<Capabilities>>;
+pub type SharedWorkspaces = Arc<Mutex<Option<Arc<Mutex<Workspaces>>>>>;
+pub type SharedKeyboard = Arc<Mutex<Option<Arc<Mutex<Keyboard>>>>>;
+
+pub trait PanelPopup {
+    fn toggle(&self);
+    fn set_trigger(&self, widget: &gtk::Widget);
+    fn set_edge(&self, edge: PanelEdge);
+}
+
+pub trait PanelModule {
+    fn name(&self) -> &'static str;
+    fn widget(&self) -> gtk::Widget;
+
+    fn expand(&self) -> bool {
+        false
+    }
+
+    fn handle_event(&self, _event: &UiEvent) {}
+
+    // A Unity launcher entry changed, see unity.rs.
+    fn launcher_entry_changed(&self, _desktop_id: &str) {}
+}
+
+#[derive(Clone)]
+pub struct PanelContext {
+    pub config: Config,
+    pub monitor: Option<gdk::Monitor>,
+    pub launcher: Rc<AppLauncher>,
+    pub wifi: Rc<WiFiPopup>,
+    pub audio: Rc<AudioMixerPopup>,
+    pub wayland_windows: SharedWindows,
+    pub wayland_seat: SharedSeat,
+    pub wayland_conn: SharedConn,
+    pub keyboard: SharedKeyboard,
+    pub capturer: SharedCapturer,
+    pub capabilities: SharedCapabilities,
+    pub workspaces: SharedWorkspaces,
+}
+
+impl PanelContext {
+    pub fn section<T: DeserializeOwned + Default>(&self, name: &str) -> T {
+        match self.config.module_config.get(name) {
+            Some(value) => serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+                eprintln!("[Panel] Invalid config for module {}: {}", name, e);
+                T::default()
+            }),
+            None => T::default(),
+        }
+    }
+
+    // The wl_output of the panel's monitor, as GDK bound it.
+    pub fn output(&self) -> Option<wayland_client::protocol::wl_output::WlOutput> {
+        let monitor = self.monitor.as_ref()?;
+        let conn = self.wayland_conn.lock().unwrap().clone()?;
+        crate::wayland::gtk_output(&conn, monitor)
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        *self.capabilities.lock().unwrap()
+    }
+
+    pub fn orientation(&self) -> gtk::Orientation {
+        self.config.edge.orientation()
+    }
+
+    // Popovers open away from the screen edge the panel sits on.
+    pub fn popover_position(&self) -> gtk::PositionType {
+        match self.config.edge {
+            PanelEdge::Bottom => gtk::PositionType::Top,
+            PanelEdge::Top => gtk::PositionType::Bottom,
+            PanelEdge::Left => gtk::PositionType::Right,
+            PanelEdge::Right => gtk::PositionType::Left,
+        }
+    }
+
+    pub fn flush(&self) {
+        if let Some(conn) = self.wayland_conn.lock().unwrap().as_ref() {
+            let _ = conn.flush();
+        }
+    }
+
+    pub fn window(&self, id: ToplevelId) -> Option<WindowHandle> {
+        let guard = self.wayland_windows.lock().unwrap();
+        let windows = guard.as_ref()?.lock().unwrap();
+        windows.iter().find(|w| w.id == id).cloned()
+    }
+
+    pub fn activate(&self, win: &WindowHandle) {
+        win.unset_minimized();
+        if let Some(seat_arc) = self.wayland_seat.lock().unwrap().as_ref() {
+            if let Some(seat) = seat_arc.lock().unwrap().as_ref() {
+                win.activate(seat);
+            }
+        }
+        self.flush();
+    }
+
+    // Tells the compositor where the windows' button is, so minimize and
+    // restore can animate toward it. Callers holding the window list lock
+    // pass the handles directly.
+    pub fn send_rectangle(&self, widget: &gtk::Widget, wins: &[&WindowHandle]) {
+        let Some(toplevel) = widget.toplevel() else {
+            return;
+        };
+        let allocation = widget.allocation();
+        if allocation.x() < 0 || wins.is_empty() {
+            return;
+        }
+        let Some(gdk_window) = toplevel.window() else {
+            return;
+        };
+        let Some(conn) = self.wayland_conn.lock().unwrap().clone() else {
+            return;
+        };
+        let Some(surface) = crate::wayland::gtk_surface(&conn, &gdk_window) else {
+            return;
+        };
+        let Some((x, y)) = widget.translate_coordinates(&toplevel, 0, 0) else {
+            return;
+        };
+
+        for win in wins {
+            win.set_rectangle(&surface, x, y, allocation.width(), allocation.height());
+        }
+        let _ = conn.flush();
+    }
+
+    pub fn set_rectangles(&self, widget: &gtk::Widget, matches: impl Fn(&WindowHandle) -> bool) {
+        let Some(windows_arc) = self.wayland_windows.lock().unwrap().clone() else {
+            return;
+        };
+        let Ok(windows) = windows_arc.lock() else {
+            return;
+        };
+        let wins: Vec<&WindowHandle> = windows.iter().filter(|w| matches(w)).collect();
+        self.send_rectangle(widget, &wins);
+    }
+
+    // The thumbnail arrives later as `UiEvent::Thumbnail`.
+    pub fn capture(&self, id: ToplevelId) -> bool {
+        let Some(win) = self.window(id) else {
+            return false;
+        };
+        match self.capturer.lock().unwrap().as_ref() {
+            Some(capturer) => capturer.capture(&win),
+            None => false,
+        }
+    }
+
+    // Taskbar click behaviour: minimize the focused window, raise any other.
+    pub fn toggle_window(&self, win: &WindowHandle) {
+        if win.activated {
+            win.set_minimized();
+            self.flush();
+        } else {
+            self.activate(win);
+        }
+    }
+}
+
+pub fn create(name: &str, ctx: &Rc<PanelContext>) -> Option<Box<dyn PanelModule>> {
+    let module: Box<dyn PanelModule> = match name {
+        "launcher" => Box::new(popup_button::PopupButtonModule::new(
+            "launcher",
+            ctx,
+            ctx.section("launcher"),
+            "view-grid-symbolic",
+            gtk::IconSize::Dnd,
+            ctx.launcher.clone(),
+        )),
+        "wifi" => Box::new(popup_button::PopupButtonModule::new(
+            "wifi",
+            ctx,
+            ctx.section("wifi"),
+            "network-wireless-symbolic",
+            gtk::IconSize::Menu,
+            ctx.wifi.clone(),
+        )),
+        "audio" => Box::new(popup_button::PopupButtonModule::new(
+            "audio",
+            ctx,
+            ctx.section("audio"),
+            "audio-volume-high-symbolic",
+            gtk::IconSize::Menu,
+            ctx.audio.clone(),
+        )),
+        "pinned" => Box::new(pinned::PinnedModule::new(ctx)),
+        "taskbar" => Box::new(taskbar::TaskbarModule::new(ctx)),
+        "keyboard" => Box::new(keyboard::KeyboardModule::new(ctx, ctx.section("keyboard"))),
+        "clock" => Box::new(clock::ClockModule::new(ctx, ctx.section("clock"))),
+        "workspaces" => Box::new(workspaces::WorkspacesModule::new(
+            ctx,
+            ctx.section("workspaces"),
+        )),
+        "show-desktop" => Box::new(show_desktop::ShowDesktopModule::new(
+            ctx,
+            ctx.section("show-desktop"),
+        )),
+        _ => return None,
+    };
+    Some(module)
+}
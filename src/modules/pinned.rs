@@ -0,0 +1,274 @@
+use gdk::EventButton;
+use gtk::prelude::*;
+use gtk::{
+    Box, Button, DestDefaults, Image, Menu, MenuItem, SeparatorMenuItem, TargetEntry, TargetFlags,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use super::badges::Badges;
+use super::{window_list, PanelContext, PanelModule};
+use crate::pins::{self, PinnedApp};
+use crate::unity;
+use crate::wayland::{ToplevelId, UiEvent, WindowHandle};
+
+// Pinned buttons, launcher tiles and .desktop files from a file manager can
+// be dropped onto the pinned lists and the taskbar.
+const PIN_TARGET: &str = "application/x-labar-pin";
+
+pub fn set_drag_source(widget: &impl IsA<gtk::Widget>, id: &str, icon: &gio::Icon) {
+    widget.drag_source_set(
+        gdk::ModifierType::BUTTON1_MASK,
+        &[TargetEntry::new(PIN_TARGET, TargetFlags::SAME_APP, 0)],
+        gdk::DragAction::MOVE,
+    );
+    widget.drag_source_set_icon_gicon(icon);
+    let id = id.to_string();
+    widget.connect_drag_data_get(move |_, _, data, _, _| {
+        data.set(&gdk::Atom::intern(PIN_TARGET), 8, id.as_bytes());
+    });
+}
+
+// Dropped items are pinned before the pin `before`, or at the end.
+pub fn set_drop_target(widget: &impl IsA<gtk::Widget>, before: Option<String>) {
+    widget.drag_dest_set(
+        DestDefaults::ALL,
+        &[
+            TargetEntry::new(PIN_TARGET, TargetFlags::SAME_APP, 0),
+            TargetEntry::new("text/uri-list", TargetFlags::OTHER_APP, 1),
+        ],
+        gdk::DragAction::MOVE | gdk::DragAction::COPY,
+    );
+    widget.connect_drag_data_received(move |_, _, _, _, data, info, _| {
+        let names: Vec<String> = if info == 0 {
+            vec![String::from_utf8_lossy(&data.data()).to_string()]
+        } else {
+            data.uris()
+                .iter()
+                .filter_map(|uri| gio::File::for_uri(uri).path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+                .filter_map(|path| {
+                    let info = gio::DesktopAppInfo::from_filename(&path)?;
+                    info.id()
+                        .map(|id| id.to_string())
+                        .or_else(|| Some(path.file_name()?.to_string_lossy().to_string()))
+                })
+                .collect()
+        };
+        for name in names.iter().filter(|n| !n.is_empty()) {
+            pins::pin_at(name, before.as_deref());
+        }
+    });
+}
+
+pub struct PinnedModule {
+    pinned_box: Box,
+    ctx: Rc<PanelContext>,
+    buttons: RefCell<Vec<(PinnedApp, Badges)>>,
+    generation: Cell<Option<u64>>,
+    running: RefCell<HashMap<String, Vec<ToplevelId>>>,
+}
+
+impl PinnedModule {
+    pub fn new(ctx: &Rc<PanelContext>) -> Self {
+        let pinned_box = Box::new(ctx.orientation(), 0);
+        set_drop_target(&pinned_box, None);
+        PinnedModule {
+            pinned_box,
+            ctx: ctx.clone(),
+            buttons: RefCell::new(Vec::new()),
+            generation: Cell::new(None),
+            running: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn refresh(&self) {
+        let Some(windows_arc) = self.ctx.wayland_windows.lock().unwrap().clone() else {
+            return;
+        };
+
+        let generation = pins::generation();
+        if self.generation.get() != Some(generation) {
+            self.rebuild(&windows_arc);
+            self.generation.set(Some(generation));
+        }
+
+        let mut running: HashMap<String, Vec<ToplevelId>> = HashMap::new();
+        match windows_arc.lock() {
+            Ok(windows) => {
+                for (app, _) in self.buttons.borrow().iter() {
+                    let ids: Vec<ToplevelId> = windows
+                        .iter()
+                        .filter(|w| app.matches(&w.app_id))
+                        .map(|w| w.id)
+                        .collect();
+                    if !ids.is_empty() {
+                        running.insert(app.id().to_string(), ids);
+                    }
+                }
+            }
+            Err(_) => return,
+        };
+        for (app, badges) in self.buttons.borrow().iter() {
+            let windows = running.get(app.id()).map_or(0, |ids| ids.len());
+            badges.apply(&unity::entry(app.id()), windows);
+            let style = badges.button.style_context();
+            if running.contains_key(app.id()) {
+                style.add_class("pinned-running");
+            } else {
+                style.remove_class("pinned-running");
+            }
+        }
+
+        // New windows of a pinned app need to learn where its button is.
+        let mut previous = self.running.borrow_mut();
+        for (app, badges) in self.buttons.borrow().iter() {
+            if let Some(ids) = running.get(app.id()) {
+                if previous.get(app.id()) != Some(ids) {
+                    self.ctx
+                        .set_rectangles(badges.button.upcast_ref(), |w| ids.contains(&w.id));
+                }
+            }
+        }
+        *previous = running;
+    }
+
+    fn rebuild(&self, windows_arc: &Arc<Mutex<Vec<WindowHandle>>>) {
+        let pinned_box = &self.pinned_box;
+        let icon_size = self.ctx.config.icon_size;
+
+        let pinned_list = pins::list();
+
+        pinned_box.foreach(|w| pinned_box.remove(w));
+        let mut buttons = self.buttons.borrow_mut();
+        buttons.clear();
+
+        for app in &pinned_list {
+            let btn = Button::new();
+            let img =
+                Image::from_gicon(&crate::desktop::icon(app.id()), gtk::IconSize::LargeToolbar);
+            img.set_pixel_size(icon_size);
+            btn.set_image(Some(&img));
+            let name = crate::desktop::lookup(app.id()).map(|info| info.name().to_string());
+            btn.set_tooltip_text(Some(name.as_deref().unwrap_or(app.id())));
+
+            let app_click = app.clone();
+            let windows_for_click = windows_arc.clone();
+            let ctx_click = self.ctx.clone();
+
+            btn.connect_clicked(move |btn| {
+                let ids: Vec<ToplevelId> = match windows_for_click.lock() {
+                    Ok(wins) => wins
+                        .iter()
+                        .filter(|w| app_click.matches(&w.app_id))
+                        .map(|w| w.id)
+                        .collect(),
+                    Err(_) => Vec::new(),
+                };
+
+                if ids.len() > 1 {
+                    window_list::popup(btn.upcast_ref(), &ctx_click, &ids);
+                } else if let Some(win) = ids.first().and_then(|id| ctx_click.window(*id)) {
+                    ctx_click.toggle_window(&win);
+                } else {
+                    let _ = Command::new("gtk-launch").arg(app_click.id()).spawn();
+                }
+            });
+
+            let app_menu = app.clone();
+            let windows_for_menu = windows_arc.clone();
+
+            btn.connect_button_press_event(move |_, event: &EventButton| {
+                if event.button() == 3 {
+                    let menu = Menu::new();
+                    crate::desktop::append_actions(&menu, app_menu.id());
+
+                    let mut window_handles: Vec<WindowHandle> = Vec::new();
+                    if let Ok(wins) = windows_for_menu.lock() {
+                        for w in wins.iter() {
+                            if app_menu.matches(&w.app_id) && w.can_control() {
+                                window_handles.push(w.clone());
+                            }
+                        }
+                    }
+
+                    if !window_handles.is_empty() {
+                        let label = if window_handles.len() > 1 {
+                            crate::locales::LOCALE
+                                .close_all_windows
+                                .replace("{}", &window_handles.len().to_string())
+                        } else {
+                            crate::locales::LOCALE.close_window.clone()
+                        };
+                        let close_item = MenuItem::with_label(&label);
+                        let handles = window_handles.clone();
+                        close_item.connect_activate(move |_| {
+                            for h in &handles {
+                                h.close();
+                            }
+                        });
+                        menu.append(&close_item);
+                        menu.append(&SeparatorMenuItem::new());
+                    }
+
+                    let unpin_item = MenuItem::with_label(&crate::locales::LOCALE.unpin);
+                    let id_unpin = app_menu.id().to_string();
+                    unpin_item.connect_activate(move |_| pins::unpin(&id_unpin));
+                    menu.append(&unpin_item);
+
+                    menu.show_all();
+                    menu.popup_at_pointer(Some(event));
+                    return glib::Propagation::Stop;
+                }
+                glib::Propagation::Proceed
+            });
+
+            let app_rect = app.clone();
+            let ctx_rect = self.ctx.clone();
+            btn.connect_size_allocate(move |btn, _| {
+                ctx_rect.set_rectangles(btn.upcast_ref(), |w| app_rect.matches(&w.app_id));
+            });
+
+            set_drag_source(&btn, app.id(), &crate::desktop::icon(app.id()));
+            set_drop_target(&btn, Some(app.id().to_string()));
+
+            let badges = Badges::new(&btn);
+            pinned_box.pack_start(&badges.overlay, false, false, 0);
+            buttons.push((app.clone(), badges));
+        }
+
+        pinned_box.show_all();
+    }
+}
+
+impl PanelModule for PinnedModule {
+    fn name(&self) -> &'static str {
+        "pinned"
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.pinned_box.clone().upcast()
+    }
+
+    fn handle_event(&self, event: &UiEvent) {
+        if let UiEvent::Refresh | UiEvent::Done = event {
+            self.refresh();
+        }
+    }
+
+    fn launcher_entry_changed(&self, desktop_id: &str) {
+        for (app, badges) in self.buttons.borrow().iter() {
+            if app.desktop_id == desktop_id || unity::matches(&app.app_id, desktop_id) {
+                let windows = self
+                    .running
+                    .borrow()
+                    .get(app.id())
+                    .map_or(0, |ids| ids.len());
+                badges.apply(&unity::entry(app.id()), windows);
+            }
+        }
+    }
+}
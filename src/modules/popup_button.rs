@@ -0,0 +1,52 @@
+use gtk::prelude::*;
+use gtk::{Button, Image};
+use serde::Deserialize;
+use std::rc::Rc;
+
+use super::{PanelContext, PanelModule, PanelPopup};
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PopupButtonConfig {
+    pub icon: Option<String>,
+}
+
+pub struct PopupButtonModule {
+    name: &'static str,
+    button: Button,
+}
+
+impl PopupButtonModule {
+    pub fn new(
+        name: &'static str,
+        ctx: &Rc<PanelContext>,
+        config: PopupButtonConfig,
+        default_icon: &str,
+        icon_size: gtk::IconSize,
+        popup: Rc<dyn PanelPopup>,
+    ) -> Self {
+        let button = Button::new();
+        let icon_name = config.icon.unwrap_or_else(|| default_icon.to_string());
+        let icon = Image::from_icon_name(Some(&icon_name), icon_size);
+        button.set_image(Some(&icon));
+
+        popup.set_edge(ctx.config.edge);
+
+        button.connect_clicked(move |btn| {
+            popup.set_trigger(btn.upcast_ref());
+            popup.toggle();
+        });
+
+        PopupButtonModule { name, button }
+    }
+}
+
+impl PanelModule for PopupButtonModule {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.button.clone().upcast()
+    }
+}
@@ -0,0 +1,260 @@
+use gtk::prelude::*;
+use gtk::Button;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::{PanelContext, PanelModule};
+use crate::wayland::{ToplevelId, UiEvent};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ShowDesktopConfig {
+    // Show the desktop while the pointer rests on the button, and bring the
+    // windows back when it leaves. Clicking during a peek keeps the desktop.
+    pub peek: bool,
+    pub peek_delay: u64,
+}
+
+impl Default for ShowDesktopConfig {
+    fn default() -> Self {
+        ShowDesktopConfig {
+            peek: false,
+            peek_delay: 500,
+        }
+    }
+}
+
+// Shared by every panel and by IPC, so toggling on one monitor and restoring
+// from another works.
+#[derive(Default)]
+struct DesktopState {
+    shown: bool,
+    peeking: bool,
+    // Windows minimized by Show Desktop, and whether each had focus.
+    stack: Vec<(ToplevelId, bool)>,
+    // Windows seen minimized while the desktop is shown. One of them coming
+    // back means the user restored it some other way.
+    hidden: HashSet<ToplevelId>,
+}
+
+thread_local! {
+    static STATE: RefCell<DesktopState> = RefCell::new(DesktopState::default());
+}
+
+pub fn is_shown() -> bool {
+    STATE.with(|s| s.borrow().shown)
+}
+
+fn is_peeking() -> bool {
+    STATE.with(|s| s.borrow().peeking)
+}
+
+fn reset() {
+    STATE.with(|s| *s.borrow_mut() = DesktopState::default());
+}
+
+pub fn toggle(ctx: &PanelContext) {
+    if is_shown() {
+        restore(ctx);
+    } else {
+        show(ctx);
+    }
+}
+
+fn show(ctx: &PanelContext) {
+    {
+        let guard = ctx.wayland_windows.lock().unwrap();
+        let Some(windows_arc) = guard.as_ref() else {
+            return;
+        };
+        let windows = windows_arc.lock().unwrap();
+        eprintln!("[Panel] Show Desktop: hiding {} windows", windows.len());
+
+        STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            *s = DesktopState::default();
+            for win in windows.iter() {
+                if win.minimized {
+                    s.hidden.insert(win.id);
+                } else {
+                    eprintln!(
+                        "[Panel] Minimizing: {} (Active: {})",
+                        win.app_id, win.activated
+                    );
+                    s.stack.push((win.id, win.activated));
+                    win.set_minimized();
+                }
+            }
+            s.shown = true;
+        });
+    }
+    ctx.flush();
+}
+
+fn restore(ctx: &PanelContext) {
+    let stack = STATE.with(|s| std::mem::take(&mut *s.borrow_mut()).stack);
+
+    let mut focused = None;
+    for (id, was_active) in stack {
+        match ctx.window(id) {
+            Some(win) => {
+                eprintln!("[Panel] Restoring: {} (Was Active: {})", id, was_active);
+                win.unset_minimized();
+                if was_active {
+                    focused = Some(win);
+                }
+            }
+            None => eprintln!("[Panel] Window {} no longer exists, skipping restore", id),
+        }
+    }
+
+    // Restoring raises each window in turn, so focus the previously active one
+    // last to put it back on top.
+    match focused {
+        Some(win) => ctx.activate(&win),
+        None => ctx.flush(),
+    }
+}
+
+// Called for every Wayland event before the panels see it. A window opened or
+// restored while the desktop is shown ends the Show Desktop state, so the next
+// click hides windows again instead of restoring stale ones.
+pub fn track(ctx: &PanelContext, event: &UiEvent) {
+    if !is_shown() {
+        return;
+    }
+    let (id, is_new) = match event {
+        UiEvent::ToplevelNew(id) => (*id, true),
+        UiEvent::ToplevelChanged { id, fields } if fields.state => (*id, false),
+        UiEvent::ToplevelClosed(id) => {
+            STATE.with(|s| s.borrow_mut().hidden.remove(id));
+            return;
+        }
+        _ => return,
+    };
+    let Some(win) = ctx.window(id) else {
+        return;
+    };
+
+    let reappeared = STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if win.minimized {
+            s.hidden.insert(id);
+            false
+        } else {
+            is_new || s.hidden.contains(&id)
+        }
+    });
+    if reappeared {
+        eprintln!(
+            "[Panel] Window {} shown meanwhile, leaving Show Desktop",
+            id
+        );
+        reset();
+    }
+}
+
+pub struct ShowDesktopModule {
+    button: Button,
+}
+
+impl ShowDesktopModule {
+    pub fn new(ctx: &Rc<PanelContext>, config: ShowDesktopConfig) -> Self {
+        let show_desktop_btn = Button::new();
+        show_desktop_btn.set_widget_name("show-desktop");
+        show_desktop_btn.set_label(if ctx.config.edge.is_vertical() {
+            " — "
+        } else {
+            " | "
+        });
+        show_desktop_btn.set_tooltip_text(Some(&crate::locales::LOCALE.show_desktop_tooltip));
+
+        let peek_timer: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+
+        let ctx_click = ctx.clone();
+        let timer_click = peek_timer.clone();
+        show_desktop_btn.connect_clicked(move |btn| {
+            if let Some(timer) = timer_click.take() {
+                timer.remove();
+            }
+            if is_peeking() {
+                STATE.with(|s| s.borrow_mut().peeking = false);
+            } else {
+                toggle(&ctx_click);
+            }
+            update_class(btn);
+        });
+
+        if config.peek {
+            let ctx_enter = ctx.clone();
+            let timer_enter = peek_timer.clone();
+            show_desktop_btn.connect_enter_notify_event(move |btn, _| {
+                if let Some(timer) = timer_enter.take() {
+                    timer.remove();
+                }
+                if is_shown() {
+                    return glib::Propagation::Proceed;
+                }
+                let ctx = ctx_enter.clone();
+                let timer = timer_enter.clone();
+                let btn = btn.clone();
+                let source = glib::timeout_add_local_once(
+                    Duration::from_millis(config.peek_delay),
+                    move || {
+                        timer.borrow_mut().take();
+                        if !is_shown() {
+                            show(&ctx);
+                            STATE.with(|s| s.borrow_mut().peeking = true);
+                            update_class(&btn);
+                        }
+                    },
+                );
+                *timer_enter.borrow_mut() = Some(source);
+                glib::Propagation::Proceed
+            });
+
+            let ctx_leave = ctx.clone();
+            let timer_leave = peek_timer.clone();
+            show_desktop_btn.connect_leave_notify_event(move |btn, _| {
+                if let Some(timer) = timer_leave.take() {
+                    timer.remove();
+                }
+                if is_peeking() {
+                    restore(&ctx_leave);
+                    update_class(btn);
+                }
+                glib::Propagation::Proceed
+            });
+        }
+
+        ShowDesktopModule {
+            button: show_desktop_btn,
+        }
+    }
+}
+
+fn update_class(button: &Button) {
+    let style = button.style_context();
+    if is_shown() {
+        style.add_class("active");
+    } else {
+        style.remove_class("active");
+    }
+}
+
+impl PanelModule for ShowDesktopModule {
+    fn name(&self) -> &'static str {
+        "show-desktop"
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.button.clone().upcast()
+    }
+
+    fn handle_event(&self, _event: &UiEvent) {
+        update_class(&self.button);
+    }
+}
@@ -0,0 +1,642 @@
+use gdk::EventButton;
+use gtk::gdk_pixbuf::{InterpType, Pixbuf};
+use gtk::prelude::*;
+use gtk::{Box, Button, Image, Label, Menu, MenuItem, Overlay, SeparatorMenuItem};
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::badges::Badges;
+use super::pinned::set_drop_target;
+use super::{window_list, PanelContext, PanelModule};
+use crate::pins;
+use crate::screencopy::Thumbnail;
+use crate::unity;
+use crate::wayland::{ToplevelId, UiEvent, WindowHandle};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Grouping {
+    Always,
+    #[default]
+    Never,
+    // Group only once one button per window no longer fits on the panel.
+    Auto,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TaskbarConfig {
+    // Show every window on every panel instead of only the ones on its output.
+    pub all_outputs: bool,
+    pub grouping: Grouping,
+    // Live window previews in the tooltip, when the compositor can capture them.
+    pub thumbnails: bool,
+    // Without window management from the compositor, say so instead of
+    // hiding the taskbar.
+    pub notice: bool,
+}
+
+impl Default for TaskbarConfig {
+    fn default() -> Self {
+        TaskbarConfig {
+            all_outputs: false,
+            grouping: Grouping::default(),
+            thumbnails: true,
+            notice: true,
+        }
+    }
+}
+
+const THUMBNAIL_SIZE: i32 = 240;
+// A capture per hover is plenty; moving across buttons should not flood the compositor.
+const THUMBNAIL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Preview {
+    pixbuf: Option<Pixbuf>,
+    requested: Instant,
+}
+
+type Previews = Rc<RefCell<HashMap<ToplevelId, Preview>>>;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum TaskKey {
+    Window(ToplevelId),
+    App(String),
+}
+
+// One button per window, or per app when grouped. Entries are keyed so a
+// title or state change only touches the affected button.
+struct TaskButton {
+    widget: Overlay,
+    button: Button,
+    image: Image,
+    badges: Badges,
+    app_id: String,
+    tooltip: String,
+    activated: bool,
+    members: Rc<RefCell<Vec<ToplevelId>>>,
+}
+
+impl TaskButton {
+    // Returns whether the set of windows behind the button changed.
+    fn update(&mut self, wins: &[&WindowHandle], icon_size: i32) -> bool {
+        let Some(first) = wins.first() else {
+            return false;
+        };
+        let ids: Vec<ToplevelId> = wins.iter().map(|w| w.id).collect();
+        let members_changed = *self.members.borrow() != ids;
+        *self.members.borrow_mut() = ids;
+
+        let tooltip = wins
+            .iter()
+            .map(|w| w.title.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if self.tooltip != tooltip {
+            self.button.set_tooltip_text(Some(&tooltip));
+            self.tooltip = tooltip;
+        }
+        if self.app_id != first.app_id {
+            self.image.set_from_gicon(
+                &crate::desktop::icon(&first.app_id),
+                gtk::IconSize::LargeToolbar,
+            );
+            self.image.set_pixel_size(icon_size);
+            self.app_id = first.app_id.clone();
+        }
+
+        let activated = wins.iter().any(|w| w.activated);
+        if self.activated != activated {
+            let style = self.button.style_context();
+            if activated {
+                style.add_class("active-window");
+            } else {
+                style.remove_class("active-window");
+            }
+            self.activated = activated;
+        }
+
+        self.badges.apply(&unity::entry(&self.app_id), wins.len());
+        members_changed
+    }
+
+    fn update_launcher_entry(&self) {
+        self.badges
+            .apply(&unity::entry(&self.app_id), self.members.borrow().len());
+    }
+}
+
+fn action_item(label: &str, ctx: &Rc<PanelContext>, action: impl Fn() + 'static) -> MenuItem {
+    let item = MenuItem::with_label(label);
+    let ctx = ctx.clone();
+    item.connect_activate(move |_| {
+        action();
+        ctx.flush();
+    });
+    item
+}
+
+fn window_menu(ctx: &Rc<PanelContext>, ids: &[ToplevelId]) -> Option<Menu> {
+    let first = ctx.window(*ids.first()?)?;
+    let app_id = first.app_id.clone();
+    let menu = Menu::new();
+    crate::desktop::append_actions(&menu, &app_id);
+
+    // Only the windows behind this button, not those on other outputs.
+    let same_app_handles: Vec<WindowHandle> = ids
+        .iter()
+        .filter_map(|id| ctx.window(*id))
+        .filter(|w| w.can_control())
+        .collect();
+
+    // Window state actions only make sense when the button stands for one window.
+    if ids.len() == 1 && first.can_control() {
+        let locale = &crate::locales::LOCALE;
+        let w = first.clone();
+        menu.append(&if first.minimized {
+            action_item(&locale.restore, ctx, move || w.unset_minimized())
+        } else {
+            action_item(&locale.minimize, ctx, move || w.set_minimized())
+        });
+        let w = first.clone();
+        menu.append(&if first.maximized {
+            action_item(&locale.unmaximize, ctx, move || w.unset_maximized())
+        } else {
+            action_item(&locale.maximize, ctx, move || w.set_maximized())
+        });
+        if first.can_fullscreen() {
+            let w = first.clone();
+            menu.append(&if first.fullscreen {
+                action_item(&locale.leave_fullscreen, ctx, move || w.unset_fullscreen())
+            } else {
+                action_item(&locale.fullscreen, ctx, move || w.set_fullscreen())
+            });
+        }
+        menu.append(&SeparatorMenuItem::new());
+    }
+
+    if same_app_handles.len() > 1 {
+        let close_all = MenuItem::with_label(
+            &crate::locales::LOCALE
+                .close_all_windows
+                .replace("{}", &same_app_handles.len().to_string()),
+        );
+        let handles = same_app_handles.clone();
+        let ctx_close = ctx.clone();
+        close_all.connect_activate(move |_| {
+            for h in &handles {
+                h.close();
+            }
+            ctx_close.flush();
+        });
+        menu.append(&close_all);
+    }
+
+    if ids.len() == 1 && first.can_control() {
+        let h = first.clone();
+        menu.append(&action_item(
+            &crate::locales::LOCALE.close_window,
+            ctx,
+            move || h.close(),
+        ));
+    }
+
+    menu.append(&SeparatorMenuItem::new());
+
+    let pin_item = MenuItem::with_label(&crate::locales::LOCALE.pin);
+    pin_item.connect_activate(move |_| {
+        pins::pin_window(&app_id);
+    });
+    menu.append(&pin_item);
+
+    Some(menu)
+}
+
+pub struct TaskbarModule {
+    task_box: Box,
+    notice: Label,
+    ctx: Rc<PanelContext>,
+    config: TaskbarConfig,
+    buttons: RefCell<HashMap<TaskKey, TaskButton>>,
+    // Set when a batch may change which buttons exist here; the next `Done`
+    // then runs a full diff instead of per-window updates.
+    dirty: Cell<bool>,
+    pinned_generation: Cell<u64>,
+    previews: Previews,
+}
+
+fn scaled(thumbnail: &Thumbnail) -> Option<Pixbuf> {
+    let pixbuf = thumbnail.pixbuf();
+    let scale = THUMBNAIL_SIZE as f64 / thumbnail.width.max(thumbnail.height) as f64;
+    if scale >= 1.0 {
+        return Some(pixbuf);
+    }
+    pixbuf.scale_simple(
+        ((thumbnail.width as f64 * scale) as i32).max(1),
+        ((thumbnail.height as f64 * scale) as i32).max(1),
+        InterpType::Bilinear,
+    )
+}
+
+fn preview_tooltip(ctx: &PanelContext, pixbuf: &Pixbuf, id: ToplevelId) -> Box {
+    let content = Box::new(gtk::Orientation::Vertical, 4);
+    content.pack_start(&Image::from_pixbuf(Some(pixbuf)), false, false, 0);
+    if let Some(win) = ctx.window(id) {
+        let title = Label::new(Some(&win.title));
+        title.set_ellipsize(pango::EllipsizeMode::End);
+        title.set_max_width_chars(32);
+        content.pack_start(&title, false, false, 0);
+    }
+    content.show_all();
+    content
+}
+
+impl TaskbarModule {
+    pub fn new(ctx: &Rc<PanelContext>) -> Self {
+        let task_box = Box::new(ctx.orientation(), 0);
+        set_drop_target(&task_box, None);
+        // Shown by `update_notice` only, so show_all on the panel leaves them be.
+        task_box.set_no_show_all(true);
+        let notice = Label::new(Some(&crate::locales::LOCALE.taskbar_unavailable));
+        notice.set_widget_name("taskbar-notice");
+        notice.set_tooltip_text(Some(&crate::locales::LOCALE.taskbar_unavailable_tooltip));
+        notice.set_no_show_all(true);
+        task_box.pack_end(&notice, false, false, 0);
+        TaskbarModule {
+            task_box,
+            notice,
+            ctx: ctx.clone(),
+            config: ctx.section("taskbar"),
+            buttons: RefCell::new(HashMap::new()),
+            dirty: Cell::new(false),
+            pinned_generation: Cell::new(pins::generation()),
+            previews: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    fn connect_previews(&self, btn: &Button, members: &Rc<RefCell<Vec<ToplevelId>>>) {
+        let single = |members: &Rc<RefCell<Vec<ToplevelId>>>| match members.borrow().as_slice() {
+            [id] => Some(*id),
+            _ => None,
+        };
+
+        let members_hover = members.clone();
+        let ctx_hover = self.ctx.clone();
+        let previews_hover = self.previews.clone();
+        btn.connect_enter_notify_event(move |_, _| {
+            if let Some(id) = single(&members_hover) {
+                let mut previews = previews_hover.borrow_mut();
+                let stale = previews
+                    .get(&id)
+                    .is_none_or(|p| p.requested.elapsed() >= THUMBNAIL_INTERVAL);
+                if stale && ctx_hover.capture(id) {
+                    let pixbuf = previews.remove(&id).and_then(|p| p.pixbuf);
+                    previews.insert(
+                        id,
+                        Preview {
+                            pixbuf,
+                            requested: Instant::now(),
+                        },
+                    );
+                }
+            }
+            glib::Propagation::Proceed
+        });
+
+        let members_tooltip = members.clone();
+        let ctx_tooltip = self.ctx.clone();
+        let previews_tooltip = self.previews.clone();
+        btn.connect_query_tooltip(move |_, _, _, _, tooltip| {
+            let Some(id) = single(&members_tooltip) else {
+                return false;
+            };
+            let previews = previews_tooltip.borrow();
+            let Some(pixbuf) = previews.get(&id).and_then(|p| p.pixbuf.as_ref()) else {
+                return false;
+            };
+            tooltip.set_custom(Some(&preview_tooltip(&ctx_tooltip, pixbuf, id)));
+            true
+        });
+    }
+
+    fn set_preview(&self, id: ToplevelId, thumbnail: Option<&Thumbnail>) {
+        let mut previews = self.previews.borrow_mut();
+        let Some(preview) = previews.get_mut(&id) else {
+            return;
+        };
+        preview.pixbuf = thumbnail.and_then(scaled);
+        drop(previews);
+        if let Some(display) = gdk::Display::default() {
+            gtk::Tooltip::trigger_tooltip_query(&display);
+        }
+    }
+
+    fn create_button(&self, first: &WindowHandle) -> TaskButton {
+        let icon_size = self.ctx.config.icon_size;
+
+        let btn = Button::new();
+        let img = Image::from_gicon(
+            &crate::desktop::icon(&first.app_id),
+            gtk::IconSize::LargeToolbar,
+        );
+        img.set_pixel_size(icon_size);
+        btn.set_image(Some(&img));
+
+        let badges = Badges::new(&btn);
+
+        let members = Rc::new(RefCell::new(Vec::new()));
+
+        let members_click = members.clone();
+        let ctx_click = self.ctx.clone();
+        btn.connect_clicked(move |btn| {
+            let ids = members_click.borrow().clone();
+            if ids.len() > 1 {
+                window_list::popup(btn.upcast_ref(), &ctx_click, &ids);
+            } else if let Some(win) = ids.first().and_then(|id| ctx_click.window(*id)) {
+                ctx_click.toggle_window(&win);
+            }
+        });
+
+        let members_menu = members.clone();
+        let ctx_menu = self.ctx.clone();
+        btn.connect_button_press_event(move |_, event: &EventButton| {
+            if event.button() == 3 {
+                let ids = members_menu.borrow().clone();
+                if let Some(menu) = window_menu(&ctx_menu, &ids) {
+                    menu.show_all();
+                    menu.popup_at_pointer(Some(event));
+                }
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        });
+
+        if self.config.thumbnails {
+            self.connect_previews(&btn, &members);
+        }
+
+        let members_rect = members.clone();
+        let ctx_rect = self.ctx.clone();
+        btn.connect_size_allocate(move |btn, _| {
+            let ids = members_rect.borrow().clone();
+            ctx_rect.set_rectangles(btn.upcast_ref(), |w| ids.contains(&w.id));
+        });
+
+        TaskButton {
+            widget: badges.overlay.clone(),
+            button: btn,
+            image: img,
+            badges,
+            app_id: first.app_id.clone(),
+            tooltip: String::new(),
+            activated: false,
+            members,
+        }
+    }
+
+    fn visible_filter(&self) -> impl Fn(&WindowHandle) -> bool {
+        let output = if self.config.all_outputs {
+            None
+        } else {
+            self.ctx.output()
+        };
+        let pinned_list = pins::list();
+
+        move |win| {
+            if pinned_list.iter().any(|p| p.matches(&win.app_id)) {
+                return false;
+            }
+            match output.as_ref() {
+                Some(output) => win.outputs.is_empty() || win.outputs.contains(output),
+                None => true,
+            }
+        }
+    }
+
+    // Estimates whether `count` ungrouped buttons would overflow the panel,
+    // from the space taken by everything else and the size of one button.
+    fn overflows(&self, count: usize) -> bool {
+        let (Some(monitor), Some(panel)) = (self.ctx.monitor.as_ref(), self.task_box.toplevel())
+        else {
+            return false;
+        };
+        let vertical = self.ctx.config.edge.is_vertical();
+        let natural = |widget: &gtk::Widget| {
+            if vertical {
+                widget.preferred_height().1
+            } else {
+                widget.preferred_width().1
+            }
+        };
+
+        let geometry = monitor.geometry();
+        let length = if vertical {
+            geometry.height()
+        } else {
+            geometry.width()
+        };
+        let slot = self
+            .buttons
+            .borrow()
+            .values()
+            .next()
+            .map(|entry| natural(entry.widget.upcast_ref()))
+            .unwrap_or(self.ctx.config.icon_size + 16);
+        let others = natural(&panel) - natural(self.task_box.upcast_ref());
+
+        others + slot * count as i32 > length
+    }
+
+    // Adds, updates or removes the button of a single ungrouped window.
+    fn sync_window(&self, id: ToplevelId) {
+        let Some(windows_arc) = self.ctx.wayland_windows.lock().unwrap().clone() else {
+            return;
+        };
+        let Ok(windows) = windows_arc.lock() else {
+            return;
+        };
+        let is_visible = self.visible_filter();
+        let key = TaskKey::Window(id);
+        let mut buttons = self.buttons.borrow_mut();
+
+        match windows.iter().find(|win| win.id == id) {
+            Some(win) if is_visible(win) => {
+                let entry = buttons.entry(key).or_insert_with(|| {
+                    let entry = self.create_button(win);
+                    self.task_box.pack_start(&entry.widget, false, false, 0);
+                    entry.widget.show_all();
+                    entry
+                });
+                if entry.update(&[win], self.ctx.config.icon_size) {
+                    self.ctx.send_rectangle(entry.button.upcast_ref(), &[win]);
+                }
+            }
+            _ => {
+                if let Some(entry) = buttons.remove(&key) {
+                    self.task_box.remove(&entry.widget);
+                }
+            }
+        }
+    }
+
+    // Refreshes whichever button currently contains the window.
+    fn update_window(&self, id: ToplevelId) {
+        let Some(windows_arc) = self.ctx.wayland_windows.lock().unwrap().clone() else {
+            return;
+        };
+        let Ok(windows) = windows_arc.lock() else {
+            return;
+        };
+        let mut buttons = self.buttons.borrow_mut();
+        let Some(entry) = buttons
+            .values_mut()
+            .find(|entry| entry.members.borrow().contains(&id))
+        else {
+            return;
+        };
+
+        let ids = entry.members.borrow().clone();
+        let wins: Vec<&WindowHandle> = ids
+            .iter()
+            .filter_map(|id| windows.iter().find(|w| w.id == *id))
+            .collect();
+        if entry.update(&wins, self.ctx.config.icon_size) {
+            self.ctx.send_rectangle(entry.button.upcast_ref(), &wins);
+        }
+    }
+
+    fn remove_window(&self, id: ToplevelId) {
+        let mut buttons = self.buttons.borrow_mut();
+        let Some(key) = buttons
+            .iter()
+            .find(|(_, entry)| entry.members.borrow().contains(&id))
+            .map(|(key, _)| key.clone())
+        else {
+            return;
+        };
+
+        if let TaskKey::Window(_) = key {
+            if let Some(entry) = buttons.remove(&key) {
+                self.task_box.remove(&entry.widget);
+            }
+        } else {
+            self.dirty.set(true);
+        }
+    }
+
+    fn update_notice(&self) {
+        let available = self.ctx.capabilities().toplevels;
+        self.notice.set_visible(!available && self.config.notice);
+        self.task_box.set_visible(available || self.config.notice);
+    }
+
+    fn refresh(&self) {
+        self.update_notice();
+        let task_box = &self.task_box;
+        let icon_size = self.ctx.config.icon_size;
+
+        let Some(windows_arc) = self.ctx.wayland_windows.lock().unwrap().clone() else {
+            return;
+        };
+        let Ok(windows) = windows_arc.lock() else {
+            return;
+        };
+        self.dirty.set(false);
+        self.pinned_generation.set(pins::generation());
+
+        let is_visible = self.visible_filter();
+        let visible: Vec<&WindowHandle> = windows.iter().filter(|win| is_visible(win)).collect();
+
+        let grouped = match self.config.grouping {
+            Grouping::Always => true,
+            Grouping::Never => false,
+            Grouping::Auto => self.overflows(visible.len()),
+        };
+
+        let mut groups: Vec<(TaskKey, Vec<&WindowHandle>)> = Vec::new();
+        for win in visible {
+            if !grouped {
+                groups.push((TaskKey::Window(win.id), vec![win]));
+                continue;
+            }
+            let key = TaskKey::App(win.app_id.clone());
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, wins)) => wins.push(win),
+                None => groups.push((key, vec![win])),
+            }
+        }
+
+        let mut buttons = self.buttons.borrow_mut();
+        buttons.retain(|key, entry| {
+            let keep = groups.iter().any(|(k, _)| k == key);
+            if !keep {
+                task_box.remove(&entry.widget);
+            }
+            keep
+        });
+
+        for (index, (key, wins)) in groups.iter().enumerate() {
+            let entry = buttons.entry(key.clone()).or_insert_with(|| {
+                let entry = self.create_button(wins[0]);
+                task_box.pack_start(&entry.widget, false, false, 0);
+                entry.widget.show_all();
+                entry
+            });
+            if entry.update(wins, icon_size) {
+                self.ctx.send_rectangle(entry.button.upcast_ref(), wins);
+            }
+            task_box.reorder_child(&entry.widget, index as i32);
+        }
+    }
+}
+
+impl PanelModule for TaskbarModule {
+    fn name(&self) -> &'static str {
+        "taskbar"
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.task_box.clone().upcast()
+    }
+
+    fn expand(&self) -> bool {
+        true
+    }
+
+    fn handle_event(&self, event: &UiEvent) {
+        match event {
+            UiEvent::Refresh => self.refresh(),
+            UiEvent::Capabilities(_) => self.update_notice(),
+            UiEvent::ToplevelNew(id) if self.config.grouping == Grouping::Never => {
+                self.sync_window(*id)
+            }
+            UiEvent::ToplevelNew(_) => self.dirty.set(true),
+            UiEvent::ToplevelChanged { fields, .. } if fields.app_id || fields.outputs => {
+                self.dirty.set(true)
+            }
+            UiEvent::ToplevelChanged { id, .. } => self.update_window(*id),
+            UiEvent::ToplevelClosed(id) => {
+                self.previews.borrow_mut().remove(id);
+                self.remove_window(*id)
+            }
+            UiEvent::Thumbnail(id, thumbnail) => self.set_preview(*id, thumbnail.as_ref()),
+            UiEvent::Done
+                if self.dirty.get() || self.pinned_generation.get() != pins::generation() =>
+            {
+                self.refresh()
+            }
+            _ => {}
+        }
+    }
+
+    fn launcher_entry_changed(&self, desktop_id: &str) {
+        for entry in self.buttons.borrow().values() {
+            if unity::matches(&entry.app_id, desktop_id) {
+                entry.update_launcher_entry();
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use gtk::prelude::*;
+use std::rc::Rc;
+
+use super::PanelContext;
+use crate::wayland::ToplevelId;
+
+// Popover listing several windows of one app, opened from grouped taskbar
+// buttons and from pinned apps with more than one window.
+pub fn popup(relative_to: &gtk::Widget, ctx: &Rc<PanelContext>, ids: &[ToplevelId]) {
+    let popover = gtk::Popover::new(Some(relative_to));
+    popover.set_widget_name("window-list");
+    // Let the popover leave the panel surface instead of being clipped to it.
+    popover.set_constrain_to(gtk::PopoverConstraint::None);
+    popover.set_position(ctx.popover_position());
+
+    let list = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+    for &id in ids {
+        let Some(win) = ctx.window(id) else {
+            continue;
+        };
+
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+
+        let title = if win.title.is_empty() {
+            win.app_id.clone()
+        } else {
+            win.title.clone()
+        };
+        let label = gtk::Label::new(Some(&title));
+        label.set_ellipsize(pango::EllipsizeMode::End);
+        label.set_max_width_chars(40);
+        label.set_xalign(0.0);
+
+        let activate_btn = gtk::Button::new();
+        activate_btn.add(&label);
+        if win.activated {
+            activate_btn.style_context().add_class("active-window");
+        }
+        let ctx_activate = ctx.clone();
+        let popover_activate = popover.clone();
+        activate_btn.connect_clicked(move |_| {
+            if let Some(win) = ctx_activate.window(id) {
+                ctx_activate.activate(&win);
+            }
+            popover_activate.popdown();
+        });
+
+        let close_btn =
+            gtk::Button::from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+        close_btn.set_tooltip_text(Some(&crate::locales::LOCALE.close_window));
+        let ctx_close = ctx.clone();
+        let popover_close = popover.clone();
+        let list_close = list.clone();
+        let row_close = row.clone();
+        close_btn.connect_clicked(move |_| {
+            if let Some(win) = ctx_close.window(id) {
+                win.close();
+                ctx_close.flush();
+            }
+            list_close.remove(&row_close);
+            if list_close.children().is_empty() {
+                popover_close.popdown();
+            }
+        });
+
+        row.pack_start(&activate_btn, true, true, 0);
+        row.pack_end(&close_btn, false, false, 0);
+        list.add(&row);
+    }
+
+    popover.add(&list);
+    list.show_all();
+    popover.popup();
+}
@@ -0,0 +1,178 @@
+use gtk::prelude::*;
+use gtk::{Box, Button, EventBox};
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::{PanelContext, PanelModule};
+use crate::wayland::UiEvent;
+use crate::workspaces::Workspace;
+use wayland_protocols::ext::workspace::v1::client::ext_workspace_handle_v1::ExtWorkspaceHandleV1;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct WorkspacesConfig {
+    // List the workspaces of every output instead of only this panel's.
+    pub all_outputs: bool,
+    // Number the buttons instead of showing the compositor's names.
+    pub numbers: bool,
+}
+
+pub struct WorkspacesModule {
+    event_box: EventBox,
+    container: Box,
+    ctx: Rc<PanelContext>,
+    config: WorkspacesConfig,
+    // Workspaces in button order, to rebuild only when the set changes.
+    shown: RefCell<Vec<ExtWorkspaceHandleV1>>,
+    buttons: RefCell<Vec<Button>>,
+}
+
+fn shown(ctx: &PanelContext, all_outputs: bool) -> Vec<Workspace> {
+    let Some(workspaces) = ctx.workspaces.lock().unwrap().clone() else {
+        return Vec::new();
+    };
+    let output = if all_outputs { None } else { ctx.output() };
+    let workspaces = workspaces.lock().unwrap();
+    workspaces.on_output(output.as_ref())
+}
+
+fn activate(ctx: &PanelContext, workspace: &Workspace) {
+    if let Some(workspaces) = ctx.workspaces.lock().unwrap().clone() {
+        workspaces.lock().unwrap().activate(workspace);
+    }
+    ctx.flush();
+}
+
+// Scrolling moves to the neighbouring workspace and stops at either end.
+fn step(ctx: &PanelContext, all_outputs: bool, forward: bool) {
+    let list = shown(ctx, all_outputs);
+    let Some(current) = list.iter().position(|w| w.active) else {
+        return;
+    };
+    let target = if forward {
+        current + 1
+    } else {
+        current.wrapping_sub(1)
+    };
+    if let Some(workspace) = list.get(target) {
+        activate(ctx, workspace);
+    }
+}
+
+impl WorkspacesModule {
+    pub fn new(ctx: &Rc<PanelContext>, config: WorkspacesConfig) -> Self {
+        let container = Box::new(ctx.orientation(), 0);
+        container.set_widget_name("workspaces");
+
+        let event_box = EventBox::new();
+        event_box.add(&container);
+        event_box.add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::SMOOTH_SCROLL_MASK);
+
+        // Touchpads send many small smooth deltas; switch once per full step.
+        let scrolled = Cell::new(0.0);
+        let ctx_scroll = ctx.clone();
+        let all_outputs = config.all_outputs;
+        event_box.connect_scroll_event(move |_, event| {
+            let forward = match event.direction() {
+                gdk::ScrollDirection::Up | gdk::ScrollDirection::Left => Some(false),
+                gdk::ScrollDirection::Down | gdk::ScrollDirection::Right => Some(true),
+                gdk::ScrollDirection::Smooth => {
+                    let (dx, dy) = event.delta();
+                    let total = scrolled.get() + dx + dy;
+                    if total.abs() >= 1.0 {
+                        scrolled.set(0.0);
+                        Some(total > 0.0)
+                    } else {
+                        scrolled.set(total);
+                        None
+                    }
+                }
+                _ => None,
+            };
+            if let Some(forward) = forward {
+                step(&ctx_scroll, all_outputs, forward);
+            }
+            glib::Propagation::Stop
+        });
+
+        WorkspacesModule {
+            event_box,
+            container,
+            ctx: ctx.clone(),
+            config,
+            shown: RefCell::new(Vec::new()),
+            buttons: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn label(&self, index: usize, workspace: &Workspace) -> String {
+        if self.config.numbers || workspace.name.is_empty() {
+            (index + 1).to_string()
+        } else {
+            workspace.name.clone()
+        }
+    }
+
+    fn refresh(&self) {
+        let list = shown(&self.ctx, self.config.all_outputs);
+        let handles: Vec<ExtWorkspaceHandleV1> = list.iter().map(|w| w.handle.clone()).collect();
+
+        if *self.shown.borrow() != handles {
+            for button in self.buttons.borrow_mut().drain(..) {
+                self.container.remove(&button);
+            }
+            for (index, workspace) in list.iter().enumerate() {
+                let button = Button::with_label(&self.label(index, workspace));
+                let ctx = self.ctx.clone();
+                let all_outputs = self.config.all_outputs;
+                let handle = workspace.handle.clone();
+                // Look the workspace up again on click; its capabilities may
+                // have changed since the button was made.
+                button.connect_clicked(move |_| {
+                    if let Some(workspace) = shown(&ctx, all_outputs)
+                        .into_iter()
+                        .find(|w| w.handle == handle)
+                    {
+                        activate(&ctx, &workspace);
+                    }
+                });
+                self.container.pack_start(&button, false, false, 0);
+                button.show();
+                self.buttons.borrow_mut().push(button);
+            }
+            *self.shown.borrow_mut() = handles;
+        }
+
+        for (index, (button, workspace)) in
+            self.buttons.borrow().iter().zip(list.iter()).enumerate()
+        {
+            button.set_label(&self.label(index, workspace));
+            button.set_tooltip_text((!workspace.name.is_empty()).then_some(&workspace.name));
+            let style = button.style_context();
+            for (class, on) in [("active", workspace.active), ("urgent", workspace.urgent)] {
+                if on {
+                    style.add_class(class);
+                } else {
+                    style.remove_class(class);
+                }
+            }
+        }
+    }
+}
+
+impl PanelModule for WorkspacesModule {
+    fn name(&self) -> &'static str {
+        "workspaces"
+    }
+
+    fn widget(&self) -> gtk::Widget {
+        self.event_box.clone().upcast()
+    }
+
+    fn handle_event(&self, event: &UiEvent) {
+        if let UiEvent::Refresh | UiEvent::Workspaces = event {
+            self.refresh();
+        }
+    }
+}
@@ -1,581 +1,421 @@
-use chrono::Local;
 use gtk::prelude::*;
-use gtk::{
-    Box, Button, Image, Label, Menu, MenuItem, Orientation, SeparatorMenuItem, Window, WindowType,
-};
+use gtk::{Box, Window, WindowType};
 use gtk_layer_shell::LayerShell;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 
 use crate::audio::AudioMixerPopup;
+use crate::config::Config;
+use crate::ipc::{Response, WindowInfo};
+use crate::keyboard::Keyboard;
 use crate::launcher::AppLauncher;
-use crate::utils::fix_icon_name;
-use crate::wayland::WindowHandle;
+use crate::modules::{self, PanelContext, PanelModule};
+use crate::screencopy::{Capturer, Thumbnail};
+use crate::wayland::{Capabilities, ToplevelId, UiEvent, WaylandClient, WindowHandle};
 use crate::wifi::WiFiPopup;
+use crate::workspaces::Workspaces;
 
-use gdk::EventButton;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
-use std::process::Command;
-use std::thread;
-
-use crate::wayland::wlr_foreign_toplevel::zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1;
-
-fn toggle_pin_app(desktop_file: &str, pin: bool) {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    let path_str = format!("{}/.config/taskbar_pinned.txt", home);
-    let path = std::path::Path::new(&path_str);
-
-    let mut lines = Vec::new();
-    if let Ok(file) = File::open(path) {
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            if let Ok(l) = line {
-                if !l.trim().is_empty() {
-                    lines.push(l);
+pub struct Labar {
+    window: Window,
+    modules: Vec<std::boxed::Box<dyn PanelModule>>,
+}
+
+impl Labar {
+    pub fn new(ctx: Rc<PanelContext>) -> Self {
+        let config = &ctx.config;
+        let window = Window::new(WindowType::Toplevel);
+
+        window.init_layer_shell();
+        if let Some(monitor) = ctx.monitor.as_ref() {
+            window.set_monitor(monitor);
+        }
+        window.set_layer(gtk_layer_shell::Layer::Top);
+        let vertical = config.edge.is_vertical();
+        // Stretch along the edge first; the panel's own edge is anchored last
+        // so the orthogonal calls cannot clear it.
+        window.set_anchor(gtk_layer_shell::Edge::Left, !vertical);
+        window.set_anchor(gtk_layer_shell::Edge::Right, !vertical);
+        window.set_anchor(gtk_layer_shell::Edge::Top, vertical);
+        window.set_anchor(gtk_layer_shell::Edge::Bottom, vertical);
+        window.set_anchor(config.edge.layer_edge(), true);
+        match config.exclusive_zone {
+            Some(zone) => window.set_exclusive_zone(zone),
+            None => window.auto_exclusive_zone_enable(),
+        }
+
+        window.set_layer_shell_margin(gtk_layer_shell::Edge::Top, config.margins.top);
+        window.set_layer_shell_margin(gtk_layer_shell::Edge::Bottom, config.margins.bottom);
+        window.set_layer_shell_margin(gtk_layer_shell::Edge::Left, config.margins.left);
+        window.set_layer_shell_margin(gtk_layer_shell::Edge::Right, config.margins.right);
+
+        if vertical {
+            window.set_size_request(config.height, -1);
+        } else {
+            window.set_size_request(-1, config.height);
+        }
+        window.set_widget_name("panel-window");
+        window.style_context().add_class(config.edge.css_class());
+        if vertical {
+            window.style_context().add_class("vertical");
+        }
+
+        let orientation = config.edge.orientation();
+        let outer_box = Box::new(orientation, 0);
+        window.add(&outer_box);
+
+        let left_box = Box::new(orientation, 0);
+        outer_box.pack_start(&left_box, false, false, 0);
+
+        let center_box = Box::new(orientation, 0);
+        outer_box.set_center_widget(Some(&center_box));
+
+        let right_box = Box::new(orientation, 0);
+        outer_box.pack_end(&right_box, false, false, 0);
+
+        let mut loaded: Vec<std::boxed::Box<dyn PanelModule>> = Vec::new();
+        let sections = [
+            (&left_box, &config.modules.left),
+            (&center_box, &config.modules.center),
+            (&right_box, &config.modules.right),
+        ];
+        for (section_box, names) in sections {
+            for name in names.iter() {
+                if loaded.iter().any(|m| m.name() == name) {
+                    eprintln!("[Panel] Module {} listed more than once, skipping", name);
+                    continue;
+                }
+                match modules::create(name, &ctx) {
+                    Some(module) => {
+                        let expand = module.expand();
+                        section_box.pack_start(&module.widget(), expand, expand, 0);
+                        loaded.push(module);
+                    }
+                    None => eprintln!("[Panel] Unknown module in config: {}", name),
                 }
             }
         }
-    }
 
-    if pin {
-        if !lines.iter().any(|l| l == desktop_file) {
-            lines.push(desktop_file.to_string());
+        Labar {
+            window,
+            modules: loaded,
         }
-    } else {
-        lines.retain(|l| l != desktop_file);
     }
 
-    if let Ok(mut file) = File::create(path) {
-        for l in lines {
-            writeln!(file, "{}", l).ok();
+    pub fn handle_event(&self, event: &UiEvent) {
+        for module in self.modules.iter() {
+            module.handle_event(event);
         }
     }
-}
 
-fn load_pinned_apps_list() -> Vec<String> {
-    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    let path = format!("{}/.config/taskbar_pinned.txt", home);
-
-    let mut apps = Vec::new();
-    if let Ok(file) = File::open(&path) {
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            if let Ok(l) = line {
-                if !l.trim().is_empty() {
-                    apps.push(l);
-                }
-            }
+    pub fn launcher_entry_changed(&self, desktop_id: &str) {
+        for module in self.modules.iter() {
+            module.launcher_entry_changed(desktop_id);
         }
     }
-    apps
+
+    pub fn show(&self) {
+        self.window.show_all();
+    }
+
+    pub fn destroy(&self) {
+        unsafe { self.window.destroy() };
+    }
 }
 
-pub struct Labar {
-    window: Window,
-    task_box: Box,
-    pinned_box: Box,
-    clock_label: Label,
-    keyboard_label: Label,
-    launcher: Rc<AppLauncher>,
-    wifi: Rc<WiFiPopup>,
-    audio: Rc<AudioMixerPopup>,
-    wayland_windows: Arc<Mutex<Option<Arc<Mutex<Vec<WindowHandle>>>>>>,
-    wayland_seat: Arc<Mutex<Option<Arc<Mutex<Option<wayland_client::protocol::wl_seat::WlSeat>>>>>>,
-    wayland_conn: Arc<Mutex<Option<wayland_client::Connection>>>,
-    keyboard_layout: Arc<Mutex<Option<Arc<Mutex<String>>>>>,
-    desktop_shown: Rc<RefCell<bool>>,
-    minimized_stack: Rc<RefCell<Vec<(String, ZwlrForeignToplevelHandleV1, bool)>>>,
+pub struct PanelManager {
+    base: RefCell<PanelContext>,
+    panels: RefCell<Vec<(gdk::Monitor, Labar)>>,
+    pending_thumbnails: RefCell<Vec<(ToplevelId, String, mpsc::Sender<Response>)>>,
+    // Set once the compositor connection is gone; main exits with an error.
+    lost_connection: Cell<bool>,
 }
 
-impl Labar {
-    pub fn new(launcher: Rc<AppLauncher>, wifi: Rc<WiFiPopup>, audio: Rc<AudioMixerPopup>) -> Self {
-        let window = Window::new(WindowType::Toplevel);
+impl PanelManager {
+    pub fn new(
+        config: &Config,
+        launcher: Rc<AppLauncher>,
+        wifi: Rc<WiFiPopup>,
+        audio: Rc<AudioMixerPopup>,
+    ) -> Rc<Self> {
+        crate::style::add_builtin("panel.css", include_bytes!("panel.css"));
+
+        Rc::new(PanelManager {
+            base: RefCell::new(PanelContext {
+                config: config.clone(),
+                monitor: None,
+                launcher,
+                wifi,
+                audio,
+                wayland_windows: Arc::new(Mutex::new(None)),
+                wayland_seat: Arc::new(Mutex::new(None)),
+                wayland_conn: Arc::new(Mutex::new(None)),
+                keyboard: Arc::new(Mutex::new(None)),
+                capturer: Arc::new(Mutex::new(None)),
+                capabilities: Arc::new(Mutex::new(Capabilities::default())),
+                workspaces: Arc::new(Mutex::new(None)),
+            }),
+            panels: RefCell::new(Vec::new()),
+            pending_thumbnails: RefCell::new(Vec::new()),
+            lost_connection: Cell::new(false),
+        })
+    }
 
-        window.init_layer_shell();
-        window.set_layer(gtk_layer_shell::Layer::Top);
-        window.set_anchor(gtk_layer_shell::Edge::Bottom, true);
-        window.set_anchor(gtk_layer_shell::Edge::Left, true);
-        window.set_anchor(gtk_layer_shell::Edge::Right, true);
-        window.auto_exclusive_zone_enable();
+    pub fn start(self: &Rc<Self>) {
+        let display = gdk::Display::default().expect("No GDK display");
+        for i in 0..display.n_monitors() {
+            if let Some(monitor) = display.monitor(i) {
+                self.add_panel(&monitor);
+            }
+        }
 
-        window.set_size_request(-1, 60);
-        window.set_widget_name("panel-window");
+        let manager = self.clone();
+        display.connect_monitor_added(move |_, monitor| {
+            manager.add_panel(monitor);
+        });
 
-        let css = r#"
-            #panel-window { background: rgba(15, 15, 15, 0.98); border-top: 1px solid rgba(255,255,255,0.1); }
-            button { background: transparent; border: none; margin: 2px; padding: 5px; border-radius: 7px; border-bottom: 4px solid transparent; transition: all 200ms ease; }
-            button:hover { background: rgba(255, 255, 255, 0.1); }
-            .active-window { border-bottom: 4px solid #00aaff; background: rgba(255,255,255,0.05); }
-            .pinned-running { border-bottom: 4px solid #00ff00; }
-            label { color: white; font-weight: bold; }
-            #keyboard-layout { color: white; font-size: 13px; font-weight: bold; padding: 8px 12px; background: rgba(255, 255, 255, 0.05); border-radius: 6px; margin: 0 8px; }
-            #show-desktop { border-radius: 0; border-left: 1px solid rgba(255,255,255,0.1); min-width: 7px; margin: 0; padding: 0; }
-            #show-desktop:hover { background: rgba(255, 255, 255, 0.2); }
-            menu { background: rgba(32, 34, 37, 0.98); border: 1px solid rgba(255, 255, 255, 0.15); border-radius: 8px; padding: 4px; }
-            menuitem { color: white; padding: 8px 12px; border-radius: 4px; }
-            menuitem:hover { background: rgba(255, 255, 255, 0.12); }
-        "#;
-        let provider = gtk::CssProvider::new();
-        provider.load_from_data(css.as_bytes()).ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
+        let manager = self.clone();
+        display.connect_monitor_removed(move |_, monitor| {
+            manager.remove_panel(monitor);
+        });
+    }
 
-        let outer_box = Box::new(Orientation::Horizontal, 0);
-        window.add(&outer_box);
+    fn add_panel(&self, monitor: &gdk::Monitor) {
+        eprintln!(
+            "[Panel] Creating panel for monitor {}",
+            monitor.model().map(|m| m.to_string()).unwrap_or_default()
+        );
+        let ctx = Rc::new(PanelContext {
+            monitor: Some(monitor.clone()),
+            ..self.base.borrow().clone()
+        });
+        let panel = Labar::new(ctx);
+        panel.handle_event(&UiEvent::Refresh);
+        panel.show();
+        self.panels.borrow_mut().push((monitor.clone(), panel));
+    }
 
-        let wayland_windows = Arc::new(Mutex::new(None::<Arc<Mutex<Vec<WindowHandle>>>>));
-        let wayland_seat = Arc::new(Mutex::new(
-            None::<Arc<Mutex<Option<wayland_client::protocol::wl_seat::WlSeat>>>>,
-        ));
-        let wayland_conn = Arc::new(Mutex::new(None::<wayland_client::Connection>));
+    fn remove_panel(&self, monitor: &gdk::Monitor) {
+        let mut panels = self.panels.borrow_mut();
+        if let Some(pos) = panels.iter().position(|(m, _)| m == monitor) {
+            let (_, panel) = panels.remove(pos);
+            eprintln!("[Panel] Monitor removed, destroying its panel");
+            panel.destroy();
+        }
+    }
 
-        let left_box = Box::new(Orientation::Horizontal, 0);
-        outer_box.pack_start(&left_box, false, false, 0);
+    pub fn reload_config(&self, config: &Config) {
+        eprintln!("[Panel] Reloading configuration");
+        self.base.borrow_mut().config = config.clone();
+        crate::desktop::set_overrides(&config.desktop_entries);
 
-        let center_box = Box::new(Orientation::Horizontal, 0);
+        let old: Vec<(gdk::Monitor, Labar)> = self.panels.borrow_mut().drain(..).collect();
+        for (monitor, panel) in old {
+            panel.destroy();
+            self.add_panel(&monitor);
+        }
+    }
 
-        let start_button = Button::new();
-        let icon = Image::from_icon_name(Some("view-grid-symbolic"), gtk::IconSize::Dnd);
-        start_button.set_image(Some(&icon));
-        let l = launcher.clone();
-        let s_btn_clone = start_button.clone();
-        start_button.connect_clicked(move |_| {
-            l.set_trigger_button(&s_btn_clone);
-            l.toggle();
-        });
-        center_box.pack_start(&start_button, false, false, 0);
+    pub fn refresh(&self) {
+        self.dispatch(&UiEvent::Refresh);
+    }
 
-        let pinned_box = Box::new(Orientation::Horizontal, 0);
-        center_box.pack_start(&pinned_box, false, false, 0);
+    pub fn dispatch(&self, event: &UiEvent) {
+        for (_, panel) in self.panels.borrow().iter() {
+            panel.handle_event(event);
+        }
+    }
 
-        let task_box = Box::new(Orientation::Horizontal, 0);
-        center_box.pack_start(&task_box, true, true, 0);
+    pub fn launcher_entry_changed(&self, desktop_id: &str) {
+        for (_, panel) in self.panels.borrow().iter() {
+            panel.launcher_entry_changed(desktop_id);
+        }
+    }
 
-        outer_box.set_center_widget(Some(&center_box));
+    pub fn toggle_popup(&self, name: &str) {
+        let base = self.base.borrow();
+        match name {
+            "launcher" => base.launcher.toggle(),
+            "wifi" => base.wifi.toggle(),
+            "audio" => base.audio.toggle(),
+            _ => eprintln!("[Panel] Unknown popup: {}", name),
+        }
+    }
 
-        let right_box = Box::new(Orientation::Horizontal, 0);
-        let keyboard_label = Label::new(Some(".."));
-        keyboard_label.set_widget_name("keyboard-layout");
-        right_box.pack_start(&keyboard_label, false, false, 0);
-
-        let wifi_btn = Button::new();
-        let w_icon = Image::from_icon_name(Some("network-wireless-symbolic"), gtk::IconSize::Menu);
-        wifi_btn.set_image(Some(&w_icon));
-        let w = wifi.clone();
-        let w_btn_clone = wifi_btn.clone();
-        wifi_btn.connect_clicked(move |_| {
-            w.set_trigger_button(&w_btn_clone);
-            w.toggle();
-        });
-        right_box.pack_start(&wifi_btn, false, false, 0);
-
-        let audio_btn = Button::new();
-        let a_icon = Image::from_icon_name(Some("audio-volume-high-symbolic"), gtk::IconSize::Menu);
-        audio_btn.set_image(Some(&a_icon));
-        let a = audio.clone();
-        let a_btn_clone = audio_btn.clone();
-        audio_btn.connect_clicked(move |_| {
-            a.set_trigger_button(&a_btn_clone);
-            a.toggle();
-        });
-        right_box.pack_start(&audio_btn, false, false, 0);
-
-        let clock_label = Label::new(Some("00:00"));
-        right_box.pack_start(&clock_label, false, false, 0);
-
-        let show_desktop_btn = Button::new();
-        show_desktop_btn.set_widget_name("show-desktop");
-        show_desktop_btn.set_label(" | ");
-        show_desktop_btn.set_tooltip_text(Some(&crate::locales::LOCALE.show_desktop_tooltip));
-
-        let desktop_shown = Rc::new(RefCell::new(false));
-        let minimized_stack: Rc<RefCell<Vec<(String, ZwlrForeignToplevelHandleV1, bool)>>> =
-            Rc::new(RefCell::new(Vec::new()));
-
-        let windows_container_clone = wayland_windows.clone();
-        let desktop_shown_clone = desktop_shown.clone();
-        let minimized_stack_clone = minimized_stack.clone();
-        let conn_clone = wayland_conn.clone();
-
-        show_desktop_btn.connect_clicked(move |_| {
-            let guard = windows_container_clone.lock().unwrap();
-            if let Some(windows_arc) = guard.as_ref() {
-                if let Ok(windows) = windows_arc.lock() {
-                    let mut shown = desktop_shown_clone.borrow_mut();
-                    let mut stack = minimized_stack_clone.borrow_mut();
-
-                    eprintln!(
-                        "[Panel] Show Desktop Clicked. Current State: Shown={}, Windows Count={}",
-                        *shown,
-                        windows.len()
-                    );
-
-                    if !*shown {
-                        stack.clear();
-
-                        let mut windows_to_hide = Vec::new();
-
-                        for win in windows.iter() {
-                            if !win.minimized {
-                                eprintln!(
-                                    "[Panel] Minimizing: {} (Active: {})",
-                                    win.app_id, win.activated
-                                );
-                                windows_to_hide.push((
-                                    win.id.clone(),
-                                    win.handle.clone(),
-                                    win.activated,
-                                ));
-                                win.handle.set_minimized();
-                            }
-                        }
-
-                        *stack = windows_to_hide;
-
-                        *shown = true;
-                    } else {
-                        let mut restored_count = 0;
-                        let mut active_window_handle: Option<ZwlrForeignToplevelHandleV1> = None;
-
-                        for (id, handle, was_active) in stack.iter() {
-                            if windows.iter().any(|w| w.id == *id) {
-                                eprintln!("[Panel] Restoring: {} (Was Active: {})", id, was_active);
-                                handle.unset_minimized();
-                                restored_count += 1;
-
-                                if *was_active {
-                                    active_window_handle = Some(handle.clone());
-                                }
-                            } else {
-                                eprintln!(
-                                    "[Panel] Window {} no longer exists, skipping restore",
-                                    id
-                                );
-                            }
-                        }
-
-                        if let Some(handle) = active_window_handle {}
-
-                        stack.clear();
-                        *shown = false;
-                    }
+    pub fn list_windows(&self) -> Vec<WindowInfo> {
+        let base = self.base.borrow();
+        let container = base.wayland_windows.lock().unwrap();
+        let Some(windows) = container.as_ref() else {
+            return Vec::new();
+        };
+        let windows = windows.lock().unwrap();
+        windows
+            .iter()
+            .map(|win| WindowInfo {
+                id: win.id,
+                title: win.title.clone(),
+                app_id: win.app_id.clone(),
+                minimized: win.minimized,
+                activated: win.activated,
+                maximized: win.maximized,
+                fullscreen: win.fullscreen,
+                identifier: win.identifier.clone(),
+            })
+            .collect()
+    }
 
-                    if let Some(conn) = conn_clone.lock().unwrap().as_ref() {
-                        let _ = conn.flush();
-                    }
-                }
+    pub fn activate_window(&self, id: ToplevelId) -> bool {
+        let base = self.base.borrow();
+        match base.window(id) {
+            Some(win) => {
+                base.activate(&win);
+                true
             }
-        });
-        right_box.pack_start(&show_desktop_btn, false, false, 0);
+            None => false,
+        }
+    }
 
-        outer_box.pack_end(&right_box, false, false, 0);
+    pub fn toggle_desktop(&self) {
+        modules::show_desktop::toggle(&self.base.borrow());
+        self.refresh();
+    }
 
-        let instance = Labar {
-            window,
-            task_box,
-            pinned_box,
-            clock_label,
-            keyboard_label,
-            launcher,
-            wifi,
-            audio,
-            wayland_windows: wayland_windows.clone(),
-            wayland_seat: wayland_seat.clone(),
-            wayland_conn: wayland_conn.clone(),
-            keyboard_layout: Arc::new(Mutex::new(None::<Arc<Mutex<String>>>)),
-            desktop_shown,
-            minimized_stack,
-        };
+    // Used by `labar msg thumbnail`; the reply is sent once the capture ends.
+    pub fn save_thumbnail(
+        &self,
+        id: ToplevelId,
+        path: String,
+        reply: mpsc::Sender<Response>,
+    ) -> Result<(), String> {
+        if self.base.borrow().window(id).is_none() {
+            return Err(format!("no window with id {}", id));
+        }
+        let already_pending = self
+            .pending_thumbnails
+            .borrow()
+            .iter()
+            .any(|(pending, _, _)| *pending == id);
+        if !already_pending && !self.base.borrow().capture(id) {
+            return Err("the compositor does not support capturing this window".to_string());
+        }
+        self.pending_thumbnails.borrow_mut().push((id, path, reply));
+        Ok(())
+    }
 
-        let label_clone = instance.clock_label.clone();
-        glib::timeout_add_seconds_local(1, move || {
-            let now = Local::now();
-            label_clone.set_text(&now.format("%H:%M\n%d.%m.%Y").to_string());
-            glib::ControlFlow::Continue
-        });
+    fn finish_thumbnails(&self, id: ToplevelId, thumbnail: Option<&Thumbnail>) {
+        let (done, waiting): (Vec<_>, Vec<_>) = self
+            .pending_thumbnails
+            .take()
+            .into_iter()
+            .partition(|(pending, _, _)| *pending == id);
+        *self.pending_thumbnails.borrow_mut() = waiting;
+
+        for (_, path, reply) in done {
+            let response = match thumbnail {
+                Some(thumbnail) => match thumbnail.pixbuf().savev(&path, "png", &[]) {
+                    Ok(()) => Response::ok(Some(serde_json::json!({
+                        "path": path,
+                        "width": thumbnail.width,
+                        "height": thumbnail.height,
+                    }))),
+                    Err(e) => Response::error(&format!("could not save {}: {}", path, e)),
+                },
+                None => Response::error("capture failed"),
+            };
+            reply.send(response).ok();
+        }
+    }
+
+    // Connects to the compositor and hands the new state to every panel.
+    pub fn connect_wayland(&self, ui_sender: glib::Sender<UiEvent>) -> Result<(), String> {
+        let client = WaylandClient::new(ui_sender)?;
+
+        self.set_wayland_windows(client.windows.clone());
+        self.set_wayland_seat(client.seat.clone());
+        self.set_wayland_conn(client.conn.clone());
+        self.set_keyboard(client.keyboard.clone());
+        self.set_capturer(client.capturer.clone());
+        self.set_workspaces(client.workspaces.clone());
+        self.set_capabilities(client.capabilities);
+        Ok(())
+    }
 
-        instance
+    pub fn lost_connection(&self) -> bool {
+        self.lost_connection.get()
     }
 
-    pub fn set_ui_receiver(&self, receiver: glib::Receiver<crate::wayland::UiEvent>) {
-        let task_box = self.task_box.clone();
-        let pinned_box = self.pinned_box.clone();
-        let wayland_windows = self.wayland_windows.clone();
-        let wayland_seat = self.wayland_seat.clone();
-        let wayland_conn = self.wayland_conn.clone();
-        let keyboard_label = self.keyboard_label.clone();
+    // GTK shares the connection, so its display is gone too and nothing can be
+    // shown any more. Drop the dead handles and exit with an error, leaving the
+    // restart to whatever supervises labar.
+    fn disconnected(self: &Rc<Self>, reason: &str) {
+        eprintln!("[Wayland] Lost the compositor connection: {}", reason);
+        {
+            let base = self.base.borrow();
+            *base.wayland_windows.lock().unwrap() = None;
+            *base.wayland_seat.lock().unwrap() = None;
+            *base.wayland_conn.lock().unwrap() = None;
+            *base.keyboard.lock().unwrap() = None;
+            *base.capturer.lock().unwrap() = None;
+            *base.workspaces.lock().unwrap() = None;
+            *base.capabilities.lock().unwrap() = Capabilities::default();
+        }
+        self.refresh();
+
+        self.lost_connection.set(true);
+        gtk::main_quit();
+    }
+
+    pub fn set_ui_receiver(self: &Rc<Self>, receiver: glib::Receiver<UiEvent>) {
+        let manager = self.clone();
 
         receiver.attach(None, move |event| {
-            match event {
-                crate::wayland::UiEvent::KeyboardLayout(layout) => {
-                    keyboard_label.set_text(&layout);
+            match &event {
+                UiEvent::Thumbnail(id, thumbnail) => {
+                    manager.finish_thumbnails(*id, thumbnail.as_ref());
                 }
-                crate::wayland::UiEvent::Refresh => {
-                    if let Some(windows_arc) = wayland_windows.lock().unwrap().as_ref() {
-                        if let Ok(windows) = windows_arc.lock() {
-                            let pinned_list = load_pinned_apps_list();
-
-                            task_box.foreach(|w| task_box.remove(w));
-
-                            pinned_box.foreach(|w| pinned_box.remove(w));
-
-                            let mut running_pinned: std::collections::HashSet<String> =
-                                std::collections::HashSet::new();
-                            for win in windows.iter() {
-                                running_pinned.insert(win.app_id.clone());
-                            }
-
-                            for app_id in &pinned_list {
-                                let btn = Button::new();
-                                let icon_name = fix_icon_name(app_id);
-                                let img = Image::from_icon_name(
-                                    Some(&icon_name),
-                                    gtk::IconSize::LargeToolbar,
-                                );
-                                img.set_pixel_size(29);
-                                btn.set_image(Some(&img));
-                                btn.set_tooltip_text(Some(app_id));
-
-                                if running_pinned.contains(app_id) {
-                                    btn.style_context().add_class("pinned-running");
-                                }
-
-                                let app_id_click = app_id.clone();
-                                let windows_for_click = windows_arc.clone();
-                                let seat_for_click = wayland_seat.clone();
-                                let conn_for_click = wayland_conn.clone();
-
-                                btn.connect_clicked(move |_| {
-                                    if let Ok(wins) = windows_for_click.lock() {
-                                        if let Some(win) =
-                                            wins.iter().find(|w| w.app_id == app_id_click)
-                                        {
-                                            if win.activated {
-                                                win.handle.set_minimized();
-                                            } else {
-                                                win.handle.unset_minimized();
-                                                if let Some(seat_arc) =
-                                                    seat_for_click.lock().unwrap().as_ref()
-                                                {
-                                                    if let Some(seat) =
-                                                        seat_arc.lock().unwrap().as_ref()
-                                                    {
-                                                        win.handle.activate(seat);
-                                                    }
-                                                }
-                                            }
-                                            if let Some(conn) =
-                                                conn_for_click.lock().unwrap().as_ref()
-                                            {
-                                                let _ = conn.flush();
-                                            }
-                                            return;
-                                        }
-                                    }
-
-                                    let _ = Command::new("gtk-launch").arg(&app_id_click).spawn();
-                                });
-
-                                let app_id_menu = app_id.clone();
-                                let pinned_box_for_menu = pinned_box.clone();
-                                let btn_for_menu = btn.clone();
-                                let windows_for_menu = windows_arc.clone();
-
-                                btn.connect_button_press_event(move |_, event: &EventButton| {
-                                    if event.button() == 3 {
-                                        let menu = Menu::new();
-
-                                        let mut window_handles: Vec<ZwlrForeignToplevelHandleV1> =
-                                            Vec::new();
-                                        if let Ok(wins) = windows_for_menu.lock() {
-                                            for w in wins.iter() {
-                                                if w.app_id == app_id_menu {
-                                                    window_handles.push(w.handle.clone());
-                                                }
-                                            }
-                                        }
-
-                                        if !window_handles.is_empty() {
-                                            let label = if window_handles.len() > 1 {
-                                                crate::locales::LOCALE.close_all_windows.replace(
-                                                    "{}",
-                                                    &window_handles.len().to_string(),
-                                                )
-                                            } else {
-                                                crate::locales::LOCALE.close_window.clone()
-                                            };
-                                            let close_item = MenuItem::with_label(&label);
-                                            let handles = window_handles.clone();
-                                            close_item.connect_activate(move |_| {
-                                                for h in &handles {
-                                                    h.close();
-                                                }
-                                            });
-                                            menu.append(&close_item);
-                                            menu.append(&SeparatorMenuItem::new());
-                                        }
-
-                                        let unpin_item =
-                                            MenuItem::with_label(&crate::locales::LOCALE.unpin);
-                                        let app_id_unpin = app_id_menu.clone();
-                                        let pb = pinned_box_for_menu.clone();
-                                        let b = btn_for_menu.clone();
-                                        unpin_item.connect_activate(move |_| {
-                                            toggle_pin_app(&app_id_unpin, false);
-                                            pb.remove(&b);
-                                        });
-                                        menu.append(&unpin_item);
-
-                                        menu.show_all();
-                                        menu.popup_at_pointer(Some(event));
-                                        return glib::Propagation::Stop;
-                                    }
-                                    glib::Propagation::Proceed
-                                });
-
-                                pinned_box.pack_start(&btn, false, false, 0);
-                            }
-
-                            for win in windows.iter() {
-                                if pinned_list.iter().any(|p| p == &win.app_id) {
-                                    continue;
-                                }
-
-                                let btn = Button::new();
-                                let icon_name = fix_icon_name(&win.app_id);
-                                let img = Image::from_icon_name(
-                                    Some(&icon_name),
-                                    gtk::IconSize::LargeToolbar,
-                                );
-                                img.set_pixel_size(29);
-                                btn.set_image(Some(&img));
-                                btn.set_tooltip_text(Some(&win.title));
-
-                                if win.activated {
-                                    btn.style_context().add_class("active-window");
-                                }
-
-                                let handle_clone = win.handle.clone();
-                                let activated = win.activated;
-                                let seat_container_inner = wayland_seat.clone();
-                                let conn_for_click = wayland_conn.clone();
-
-                                btn.connect_clicked(move |_| {
-                                    if activated {
-                                        handle_clone.set_minimized();
-                                    } else {
-                                        handle_clone.unset_minimized();
-                                        if let Some(seat_arc) =
-                                            seat_container_inner.lock().unwrap().as_ref()
-                                        {
-                                            if let Some(seat) = seat_arc.lock().unwrap().as_ref() {
-                                                handle_clone.activate(seat);
-                                            }
-                                        }
-                                    }
-                                    if let Some(conn) = conn_for_click.lock().unwrap().as_ref() {
-                                        let _ = conn.flush();
-                                    }
-                                });
-
-                                let app_id_clone = win.app_id.clone();
-                                let handle_for_menu = win.handle.clone();
-                                let windows_for_menu = windows_arc.clone();
-
-                                btn.connect_button_press_event(move |_, event: &EventButton| {
-                                    if event.button() == 3 {
-                                        let menu = Menu::new();
-
-                                        let mut same_app_handles: Vec<ZwlrForeignToplevelHandleV1> =
-                                            Vec::new();
-                                        if let Ok(wins) = windows_for_menu.lock() {
-                                            for w in wins.iter() {
-                                                if w.app_id == app_id_clone {
-                                                    same_app_handles.push(w.handle.clone());
-                                                }
-                                            }
-                                        }
-
-                                        if same_app_handles.len() > 1 {
-                                            let close_all = MenuItem::with_label(
-                                                &crate::locales::LOCALE.close_all_windows.replace(
-                                                    "{}",
-                                                    &same_app_handles.len().to_string(),
-                                                ),
-                                            );
-                                            let handles = same_app_handles.clone();
-                                            close_all.connect_activate(move |_| {
-                                                for h in &handles {
-                                                    h.close();
-                                                }
-                                            });
-                                            menu.append(&close_all);
-                                        }
-
-                                        let close_item = MenuItem::with_label(
-                                            &crate::locales::LOCALE.close_window,
-                                        );
-                                        let h = handle_for_menu.clone();
-                                        close_item.connect_activate(move |_| {
-                                            h.close();
-                                        });
-                                        menu.append(&close_item);
-
-                                        menu.append(&SeparatorMenuItem::new());
-
-                                        let pin_item =
-                                            MenuItem::with_label(&crate::locales::LOCALE.pin);
-                                        let app_id_pin = app_id_clone.clone();
-                                        pin_item.connect_activate(move |_| {
-                                            toggle_pin_app(&app_id_pin, true);
-                                        });
-                                        menu.append(&pin_item);
-
-                                        menu.show_all();
-                                        menu.popup_at_pointer(Some(event));
-                                        return glib::Propagation::Stop;
-                                    }
-                                    glib::Propagation::Proceed
-                                });
-
-                                task_box.pack_start(&btn, false, false, 0);
-                            }
-
-                            task_box.show_all();
-                            pinned_box.show_all();
-                        }
-                    }
+                UiEvent::Capabilities(capabilities) => manager.set_capabilities(*capabilities),
+                UiEvent::Disconnected(reason) => {
+                    manager.disconnected(reason);
+                    return glib::ControlFlow::Continue;
                 }
+                _ => {}
+            }
+            modules::show_desktop::track(&manager.base.borrow(), &event);
+            for (_, panel) in manager.panels.borrow().iter() {
+                panel.handle_event(&event);
             }
             glib::ControlFlow::Continue
         });
     }
 
     pub fn set_wayland_windows(&self, windows: Arc<Mutex<Vec<WindowHandle>>>) {
-        *self.wayland_windows.lock().unwrap() = Some(windows);
+        *self.base.borrow().wayland_windows.lock().unwrap() = Some(windows);
     }
 
     pub fn set_wayland_seat(
         &self,
         seat: Arc<Mutex<Option<wayland_client::protocol::wl_seat::WlSeat>>>,
     ) {
-        *self.wayland_seat.lock().unwrap() = Some(seat);
+        *self.base.borrow().wayland_seat.lock().unwrap() = Some(seat);
     }
 
     pub fn set_wayland_conn(&self, conn: wayland_client::Connection) {
-        *self.wayland_conn.lock().unwrap() = Some(conn);
+        *self.base.borrow().wayland_conn.lock().unwrap() = Some(conn);
     }
 
-    pub fn set_keyboard_layout(&self, layout: Arc<Mutex<String>>) {
-        *self.keyboard_layout.lock().unwrap() = Some(layout);
+    pub fn set_capturer(&self, capturer: Arc<Capturer>) {
+        *self.base.borrow().capturer.lock().unwrap() = Some(capturer);
     }
 
-    pub fn show(&self) {
-        self.window.show_all();
+    pub fn set_workspaces(&self, workspaces: Arc<Mutex<Workspaces>>) {
+        *self.base.borrow().workspaces.lock().unwrap() = Some(workspaces);
+    }
+
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        *self.base.borrow().capabilities.lock().unwrap() = capabilities;
+    }
+
+    pub fn set_keyboard(&self, keyboard: Arc<Mutex<Keyboard>>) {
+        *self.base.borrow().keyboard.lock().unwrap() = Some(keyboard);
     }
 }
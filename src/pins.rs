@@ -0,0 +1,397 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs;
+use std::sync::Mutex;
+
+use gio::prelude::*;
+
+// Apps pinned to the taskbar and the launcher, in display order. Each entry
+// keeps the desktop id (to launch it and find its icon) and the app_id its
+// windows use (to tell whether it is running). Either may be empty when it
+// could not be resolved.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PinnedApp {
+    pub desktop_id: String,
+    pub app_id: String,
+}
+
+impl PinnedApp {
+    fn from_name(name: &str) -> Self {
+        match crate::desktop::lookup(name) {
+            Some(info) => PinnedApp {
+                desktop_id: info.id().map(|id| id.to_string()).unwrap_or_default(),
+                app_id: if name.ends_with(".desktop") {
+                    crate::desktop::app_id_of(&info)
+                } else {
+                    name.to_string()
+                },
+            },
+            None => PinnedApp {
+                desktop_id: String::new(),
+                app_id: name.trim_end_matches(".desktop").to_string(),
+            },
+        }
+    }
+
+    // Stable key for the entry, used by `unpin` and `move_to`.
+    pub fn id(&self) -> &str {
+        if self.desktop_id.is_empty() {
+            &self.app_id
+        } else {
+            &self.desktop_id
+        }
+    }
+
+    pub fn matches(&self, app_id: &str) -> bool {
+        if !self.app_id.is_empty() && self.app_id == app_id {
+            return true;
+        }
+        !self.desktop_id.is_empty()
+            && crate::desktop::lookup(app_id)
+                .and_then(|info| info.id())
+                .is_some_and(|id| id == self.desktop_id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct PinnedFile {
+    apps: Vec<PinnedApp>,
+}
+
+struct Store {
+    apps: Option<Vec<PinnedApp>>,
+    generation: u64,
+}
+
+lazy_static! {
+    static ref STORE: Mutex<Store> = Mutex::new(Store {
+        apps: None,
+        generation: 0,
+    });
+}
+
+thread_local! {
+    static LISTENERS: RefCell<Vec<Box<dyn Fn()>>> = RefCell::new(Vec::new());
+}
+
+fn home() -> String {
+    std::env::var("HOME").unwrap_or_else(|_| ".".to_string())
+}
+
+fn store_path() -> String {
+    format!("{}/.config/labar/pins.json", home())
+}
+
+// Before the shared store, the taskbar kept app_ids and desktop ids in a text
+// file and the launcher kept `[{"desktop_file": ...}]` in pinned.json.
+fn legacy_paths() -> [String; 2] {
+    [
+        format!("{}/.config/taskbar_pinned.txt", home()),
+        format!("{}/.config/labar/pinned.json", home()),
+    ]
+}
+
+fn legacy_entries(legacy: &[String; 2]) -> Vec<String> {
+    #[derive(Deserialize)]
+    struct LegacyApp {
+        desktop_file: String,
+    }
+
+    let [taskbar, launcher] = legacy;
+    let mut names: Vec<String> = fs::read_to_string(taskbar)
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    if let Some(apps) = fs::read_to_string(launcher)
+        .ok()
+        .and_then(|c| serde_json::from_str::<Vec<LegacyApp>>(&c).ok())
+    {
+        names.extend(apps.into_iter().map(|a| a.desktop_file));
+    }
+    names
+}
+
+// Imports the legacy files into the store once, then renames them to .bak so
+// they are never read again.
+fn migrate(store: &str, legacy: &[String; 2]) -> Vec<PinnedApp> {
+    let mut apps: Vec<PinnedApp> = Vec::new();
+    for name in legacy_entries(legacy) {
+        let app = PinnedApp::from_name(&name);
+        if !apps.iter().any(|a| a.id() == app.id()) {
+            apps.push(app);
+        }
+    }
+    if apps.is_empty() {
+        return apps;
+    }
+
+    eprintln!("[Pinned] Migrated {} pinned apps to {}", apps.len(), store);
+    save_to(store, &apps);
+    for path in legacy {
+        if std::path::Path::new(path).exists() {
+            if let Err(e) = fs::rename(path, format!("{}.bak", path)) {
+                eprintln!("[Pinned] Failed to retire {}: {}", path, e);
+            }
+        }
+    }
+    apps
+}
+
+fn load() -> Vec<PinnedApp> {
+    load_from(&store_path(), &legacy_paths())
+}
+
+fn load_from(store: &str, legacy: &[String; 2]) -> Vec<PinnedApp> {
+    let content = match fs::read_to_string(store) {
+        Ok(content) => content,
+        Err(_) => return migrate(store, legacy),
+    };
+    match serde_json::from_str::<PinnedFile>(&content) {
+        Ok(file) => file.apps,
+        Err(e) => {
+            // Keep the broken file for the user instead of silently replacing
+            // it on the next change.
+            let backup = format!("{}.bak", store);
+            eprintln!("[Pinned] Invalid {}: {}; moved it to {}", store, e, backup);
+            let _ = fs::rename(store, backup);
+            Vec::new()
+        }
+    }
+}
+
+fn save(apps: &[PinnedApp]) {
+    save_to(&store_path(), apps);
+}
+
+fn save_to(path: &str, apps: &[PinnedApp]) {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let file = PinnedFile {
+        apps: apps.to_vec(),
+    };
+    if let Err(e) = fs::write(
+        path,
+        serde_json::to_string_pretty(&file).unwrap_or_default(),
+    ) {
+        eprintln!("[Pinned] Failed to write {}: {}", path, e);
+    }
+}
+
+fn update(change: impl FnOnce(&mut Vec<PinnedApp>) -> bool) {
+    {
+        let mut store = STORE.lock().unwrap();
+        let apps = store.apps.get_or_insert_with(load);
+        if !change(apps) {
+            return;
+        }
+        save(apps);
+        store.generation += 1;
+    }
+    LISTENERS.with(|l| l.borrow().iter().for_each(|listener| listener()));
+}
+
+pub fn list() -> Vec<PinnedApp> {
+    STORE.lock().unwrap().apps.get_or_insert_with(load).clone()
+}
+
+pub fn generation() -> u64 {
+    STORE.lock().unwrap().generation
+}
+
+// Called on the GTK thread after every change to the list.
+pub fn connect_changed(listener: impl Fn() + 'static) {
+    LISTENERS.with(|l| l.borrow_mut().push(Box::new(listener)));
+}
+
+// Pins a launcher entry.
+pub fn pin_desktop(desktop_id: &str) {
+    let app = PinnedApp::from_name(desktop_id);
+    update(|apps| {
+        if apps.iter().any(|a| a.id() == app.id()) {
+            return false;
+        }
+        apps.push(app);
+        true
+    });
+}
+
+// Pins the app of a running window.
+pub fn pin_window(app_id: &str) {
+    let app = PinnedApp::from_name(app_id);
+    update(|apps| {
+        if apps.iter().any(|a| a.id() == app.id() || a.matches(app_id)) {
+            return false;
+        }
+        apps.push(app);
+        true
+    });
+}
+
+pub fn unpin(id: &str) {
+    update(|apps| {
+        let before = apps.len();
+        apps.retain(|a| a.id() != id);
+        apps.len() != before
+    });
+}
+
+// Pins `name` (a desktop id or app_id), or moves it if it is already pinned,
+// so that it sits before the entry `before`, or last.
+pub fn pin_at(name: &str, before: Option<&str>) {
+    if before == Some(name) {
+        return;
+    }
+    let app = PinnedApp::from_name(name);
+    update(|apps| place(apps, app, name, before));
+}
+
+// Moves the entry for `name` (or inserts `app`) before `before`, or to the
+// end when `before` is None or not pinned. Returns whether the list changed.
+fn place(apps: &mut Vec<PinnedApp>, app: PinnedApp, name: &str, before: Option<&str>) -> bool {
+    let old = apps.clone();
+    let moved = match apps
+        .iter()
+        .position(|a| a.id() == name || a.id() == app.id())
+    {
+        Some(from) => apps.remove(from),
+        None => app,
+    };
+    let index = before
+        .and_then(|b| apps.iter().position(|a| a.id() == b))
+        .unwrap_or(apps.len());
+    apps.insert(index, moved);
+    *apps != old
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory per test, since the tests run in parallel.
+    fn scratch(name: &str) -> (String, [String; 2]) {
+        let dir = std::env::temp_dir().join(format!("labar-pins-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = |file: &str| dir.join(file).to_string_lossy().to_string();
+        (
+            path("pins.json"),
+            [path("taskbar_pinned.txt"), path("pinned.json")],
+        )
+    }
+
+    fn app(id: &str) -> PinnedApp {
+        PinnedApp {
+            desktop_id: format!("{}.desktop", id),
+            app_id: id.to_string(),
+        }
+    }
+
+    fn ids(apps: &[PinnedApp]) -> Vec<&str> {
+        apps.iter().map(|a| a.id()).collect()
+    }
+
+    #[test]
+    fn migrates_both_legacy_files_once() {
+        let (store, legacy) = scratch("migrate");
+        fs::write(&legacy[0], "labar-test-editor\n\nlabar-test-player\n").unwrap();
+        fs::write(
+            &legacy[1],
+            r#"[{"desktop_file": "labar-test-player.desktop"},
+                {"desktop_file": "labar-test-viewer.desktop"}]"#,
+        )
+        .unwrap();
+
+        let apps = load_from(&store, &legacy);
+        assert_eq!(
+            ids(&apps),
+            [
+                "labar-test-editor",
+                "labar-test-player",
+                "labar-test-viewer"
+            ]
+        );
+        for path in &legacy {
+            assert!(!std::path::Path::new(path).exists());
+            assert!(std::path::Path::new(&format!("{}.bak", path)).exists());
+        }
+
+        // The store is read from now on, even if a legacy file comes back.
+        fs::write(&legacy[0], "labar-test-other\n").unwrap();
+        assert_eq!(load_from(&store, &legacy), apps);
+    }
+
+    #[test]
+    fn nothing_to_migrate_writes_no_store() {
+        let (store, legacy) = scratch("empty");
+        assert!(load_from(&store, &legacy).is_empty());
+        assert!(!std::path::Path::new(&store).exists());
+    }
+
+    #[test]
+    fn corrupt_store_is_moved_aside() {
+        let (store, legacy) = scratch("corrupt");
+        fs::write(&store, "{\"apps\": [").unwrap();
+        fs::write(&legacy[0], "labar-test-editor\n").unwrap();
+
+        assert!(load_from(&store, &legacy).is_empty());
+        assert!(!std::path::Path::new(&store).exists());
+        assert_eq!(
+            fs::read_to_string(format!("{}.bak", store)).unwrap(),
+            "{\"apps\": ["
+        );
+        // Legacy pins are not imported over a store the user still has.
+        assert!(std::path::Path::new(&legacy[0]).exists());
+    }
+
+    #[test]
+    fn saved_store_loads_back() {
+        let (store, legacy) = scratch("roundtrip");
+        let apps = vec![app("a"), app("b")];
+        save_to(&store, &apps);
+        assert_eq!(load_from(&store, &legacy), apps);
+    }
+
+    #[test]
+    fn place_moves_forward_and_backward() {
+        let mut apps = vec![app("a"), app("b"), app("c"), app("d")];
+        assert!(place(&mut apps, app("a"), "a.desktop", Some("d.desktop")));
+        assert_eq!(
+            ids(&apps),
+            ["b.desktop", "c.desktop", "a.desktop", "d.desktop"]
+        );
+
+        assert!(place(&mut apps, app("d"), "d.desktop", Some("b.desktop")));
+        assert_eq!(
+            ids(&apps),
+            ["d.desktop", "b.desktop", "c.desktop", "a.desktop"]
+        );
+
+        // Already in place.
+        assert!(!place(&mut apps, app("c"), "c.desktop", Some("a.desktop")));
+    }
+
+    #[test]
+    fn place_out_of_range_goes_last() {
+        let mut apps = vec![app("a"), app("b"), app("c")];
+        assert!(place(
+            &mut apps,
+            app("a"),
+            "a.desktop",
+            Some("missing.desktop")
+        ));
+        assert_eq!(ids(&apps), ["b.desktop", "c.desktop", "a.desktop"]);
+
+        assert!(!place(&mut apps, app("a"), "a.desktop", None));
+
+        assert!(place(&mut apps, app("e"), "e.desktop", Some("b.desktop")));
+        assert_eq!(
+            ids(&apps),
+            ["e.desktop", "b.desktop", "c.desktop", "a.desktop"]
+        );
+    }
+}
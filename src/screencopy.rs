@@ -0,0 +1,470 @@
+use gtk::gdk_pixbuf;
+use memmap2::MmapMut;
+use std::fs::File;
+use std::os::unix::io::AsFd;
+use std::sync::{Arc, Mutex};
+use wayland_client::globals::GlobalList;
+use wayland_client::protocol::{wl_buffer, wl_shm, wl_shm_pool};
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, WEnum};
+
+use wayland_protocols::ext::image_capture_source::v1::client::{
+    ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+    ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+};
+use wayland_protocols::ext::image_copy_capture::v1::client::{
+    ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+    ext_image_copy_capture_manager_v1::{ExtImageCopyCaptureManagerV1, Options},
+    ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use crate::wayland::{AppData, ToplevelId, UiEvent, WindowHandle};
+
+// RGBA pixels, ready for a GdkPixbuf.
+#[derive(Clone)]
+pub struct Thumbnail {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<u8>,
+}
+
+impl Thumbnail {
+    pub fn pixbuf(&self) -> gdk_pixbuf::Pixbuf {
+        gdk_pixbuf::Pixbuf::from_bytes(
+            &glib::Bytes::from(&self.data),
+            gdk_pixbuf::Colorspace::Rgb,
+            true,
+            8,
+            self.width,
+            self.height,
+            self.width * 4,
+        )
+    }
+}
+
+impl std::fmt::Debug for Thumbnail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Thumbnail({}x{})", self.width, self.height)
+    }
+}
+
+#[derive(Debug)]
+pub struct Capturer {
+    conn: Connection,
+    qh: QueueHandle<AppData>,
+    pub shm: Option<wl_shm::WlShm>,
+    copy_manager: Option<ExtImageCopyCaptureManagerV1>,
+    source_manager: Option<ExtForeignToplevelImageCaptureSourceManagerV1>,
+    screencopy_manager: Option<ZwlrScreencopyManagerV1>,
+}
+
+impl Capturer {
+    pub fn bind(globals: &GlobalList, conn: &Connection, qh: &QueueHandle<AppData>) -> Self {
+        let shm = globals.bind::<wl_shm::WlShm, _, _>(qh, 1..=1, ()).ok();
+        let copy_manager = globals
+            .bind::<ExtImageCopyCaptureManagerV1, _, _>(qh, 1..=1, ())
+            .ok();
+        let source_manager = globals
+            .bind::<ExtForeignToplevelImageCaptureSourceManagerV1, _, _>(qh, 1..=1, ())
+            .ok();
+        let screencopy_manager = globals
+            .bind::<ZwlrScreencopyManagerV1, _, _>(qh, 1..=3, ())
+            .ok();
+
+        eprintln!(
+            "[Screencopy] ext-image-copy-capture: {}, wlr-screencopy: {}",
+            copy_manager.is_some() && source_manager.is_some(),
+            screencopy_manager.is_some()
+        );
+
+        Capturer {
+            conn: conn.clone(),
+            qh: qh.clone(),
+            shm,
+            copy_manager,
+            source_manager,
+            screencopy_manager,
+        }
+    }
+
+    // Starts an asynchronous capture; the result arrives as
+    // `UiEvent::Thumbnail`. Returns false when no protocol can serve it.
+    pub fn capture(&self, win: &WindowHandle) -> bool {
+        if self.shm.is_none() {
+            return false;
+        }
+        let job = Arc::new(CaptureJob {
+            id: win.id,
+            state: Mutex::new(JobState::default()),
+        });
+
+        if let (Some(copy_manager), Some(source_manager), Some(handle)) = (
+            self.copy_manager.as_ref(),
+            self.source_manager.as_ref(),
+            win.ext.clone(),
+        ) {
+            let source = source_manager.create_source(&handle, &self.qh, ());
+            let session =
+                copy_manager.create_session(&source, Options::empty(), &self.qh, job.clone());
+            let mut state = job.state.lock().unwrap();
+            state.source = Some(source);
+            state.session = Some(session);
+        } else if let Some(manager) = self.screencopy_manager.as_ref() {
+            // wlr-screencopy can only capture output regions, and neither
+            // toplevel protocol says where a window is. The one region known
+            // to be exactly the window is the output of a focused fullscreen
+            // window; anything else would preview other windows too.
+            let Some(output) = win
+                .outputs
+                .first()
+                .filter(|_| win.activated && win.fullscreen)
+            else {
+                return false;
+            };
+            manager.capture_output(0, output, &self.qh, job.clone());
+        } else {
+            return false;
+        }
+
+        let _ = self.conn.flush();
+        true
+    }
+}
+
+struct ShmBuffer {
+    _file: File,
+    mmap: MmapMut,
+    pool: wl_shm_pool::WlShmPool,
+    buffer: wl_buffer::WlBuffer,
+}
+
+impl ShmBuffer {
+    fn new(
+        shm: &wl_shm::WlShm,
+        width: u32,
+        height: u32,
+        stride: u32,
+        format: wl_shm::Format,
+        qh: &QueueHandle<AppData>,
+    ) -> Option<Self> {
+        let size = (stride * height) as usize;
+        let file = shm_file(size)?;
+        let mmap = unsafe { MmapMut::map_mut(&file) }.ok()?;
+        let pool = shm.create_pool(file.as_fd(), size as i32, qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            stride as i32,
+            format,
+            qh,
+            (),
+        );
+        Some(ShmBuffer {
+            _file: file,
+            mmap,
+            pool,
+            buffer,
+        })
+    }
+
+    fn destroy(&self) {
+        self.buffer.destroy();
+        self.pool.destroy();
+    }
+}
+
+// An unlinked file in XDG_RUNTIME_DIR, which is tmpfs on every system labwc
+// runs on, so it behaves like a memfd without needing libc.
+fn shm_file(size: usize) -> Option<File> {
+    let dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let path = format!("{}/labar-shm-{}-{}", dir, std::process::id(), nanos);
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .ok()?;
+    let _ = std::fs::remove_file(&path);
+    file.set_len(size as u64).ok()?;
+    Some(file)
+}
+
+// How the captured pixels are laid out in the buffer.
+#[derive(Default, Clone, Copy)]
+struct Frame {
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: Option<wl_shm::Format>,
+    y_invert: bool,
+}
+
+#[derive(Default)]
+struct JobState {
+    frame: Frame,
+    started: bool,
+    finished: bool,
+    buffer: Option<ShmBuffer>,
+    source: Option<ExtImageCaptureSourceV1>,
+    session: Option<ExtImageCopyCaptureSessionV1>,
+}
+
+pub struct CaptureJob {
+    id: ToplevelId,
+    state: Mutex<JobState>,
+}
+
+fn accepted_format(format: WEnum<wl_shm::Format>) -> Option<wl_shm::Format> {
+    match format {
+        WEnum::Value(f @ (wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888)) => Some(f),
+        _ => None,
+    }
+}
+
+// Both formats are little-endian B, G, R, A/X in memory.
+fn to_rgba(frame: &Frame, buffer: &ShmBuffer) -> Option<Thumbnail> {
+    let (width, height, stride) = (
+        frame.width as usize,
+        frame.height as usize,
+        frame.stride as usize,
+    );
+    let opaque = frame.format == Some(wl_shm::Format::Xrgb8888);
+
+    let mut data = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        let src_row = if frame.y_invert {
+            height - 1 - row
+        } else {
+            row
+        };
+        let line = buffer
+            .mmap
+            .get(src_row * stride..src_row * stride + width * 4)?;
+        for px in line.chunks_exact(4) {
+            data.extend_from_slice(&[px[2], px[1], px[0], if opaque { 255 } else { px[3] }]);
+        }
+    }
+
+    Some(Thumbnail {
+        width: width as i32,
+        height: height as i32,
+        data,
+    })
+}
+
+fn finish(state: &mut JobState, id: ToplevelId, ok: bool, ui_sender: &glib::Sender<UiEvent>) {
+    if state.finished {
+        return;
+    }
+    state.finished = true;
+
+    let buffer = state.buffer.take();
+    if let Some(buffer) = buffer.as_ref() {
+        buffer.destroy();
+    }
+    if let Some(session) = state.session.take() {
+        session.destroy();
+    }
+    if let Some(source) = state.source.take() {
+        source.destroy();
+    }
+
+    let Some(buffer) = buffer.filter(|_| ok) else {
+        ui_sender.send(UiEvent::Thumbnail(id, None)).ok();
+        return;
+    };
+    // Converting a full output takes a while; keep it off the dispatch
+    // thread. The mapping outlives the destroyed wl_buffer.
+    let frame = state.frame;
+    let ui_sender = ui_sender.clone();
+    std::thread::spawn(move || {
+        let thumbnail = to_rgba(&frame, &buffer);
+        ui_sender.send(UiEvent::Thumbnail(id, thumbnail)).ok();
+    });
+}
+
+fn allocate(state: &mut JobState, shm: &wl_shm::WlShm, qh: &QueueHandle<AppData>) -> bool {
+    let frame = &mut state.frame;
+    let Some(format) = frame.format else {
+        return false;
+    };
+    if frame.width == 0 || frame.height == 0 {
+        return false;
+    }
+    if frame.stride == 0 {
+        frame.stride = frame.width * 4;
+    }
+    state.buffer = ShmBuffer::new(shm, frame.width, frame.height, frame.stride, format, qh);
+    state.buffer.is_some()
+}
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, Arc<CaptureJob>> for AppData {
+    fn event(
+        state: &mut AppData,
+        proxy: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        job: &Arc<CaptureJob>,
+        _conn: &Connection,
+        qh: &QueueHandle<AppData>,
+    ) {
+        let mut job_state = job.state.lock().unwrap();
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                job_state.frame.width = width;
+                job_state.frame.height = height;
+            }
+            ext_image_copy_capture_session_v1::Event::ShmFormat { format }
+                if job_state.frame.format.is_none() =>
+            {
+                job_state.frame.format = accepted_format(format);
+            }
+            ext_image_copy_capture_session_v1::Event::Done if !job_state.started => {
+                job_state.started = true;
+                let Some(shm) = state.shm.clone() else {
+                    return finish(&mut job_state, job.id, false, &state.ui_sender);
+                };
+                if !allocate(&mut job_state, &shm, qh) {
+                    return finish(&mut job_state, job.id, false, &state.ui_sender);
+                }
+                let buffer = &job_state.buffer.as_ref().unwrap().buffer;
+                let frame = proxy.create_frame(qh, job.clone());
+                frame.attach_buffer(buffer);
+                let size = job_state.frame;
+                frame.damage_buffer(0, 0, size.width as i32, size.height as i32);
+                frame.capture();
+            }
+            ext_image_copy_capture_session_v1::Event::Stopped => {
+                finish(&mut job_state, job.id, false, &state.ui_sender);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, Arc<CaptureJob>> for AppData {
+    fn event(
+        state: &mut AppData,
+        proxy: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        job: &Arc<CaptureJob>,
+        _conn: &Connection,
+        _qh: &QueueHandle<AppData>,
+    ) {
+        let mut job_state = job.state.lock().unwrap();
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Ready => {
+                proxy.destroy();
+                finish(&mut job_state, job.id, true, &state.ui_sender);
+            }
+            ext_image_copy_capture_frame_v1::Event::Failed { .. } => {
+                proxy.destroy();
+                finish(&mut job_state, job.id, false, &state.ui_sender);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, Arc<CaptureJob>> for AppData {
+    fn event(
+        state: &mut AppData,
+        proxy: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        job: &Arc<CaptureJob>,
+        _conn: &Connection,
+        qh: &QueueHandle<AppData>,
+    ) {
+        let mut job_state = job.state.lock().unwrap();
+        // Before version 3 there is no buffer_done; the single buffer event
+        // is all the compositor will offer.
+        let copy_now = match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                if job_state.frame.format.is_none() {
+                    if let Some(format) = accepted_format(format) {
+                        job_state.frame = Frame {
+                            format: Some(format),
+                            width,
+                            height,
+                            stride,
+                            ..job_state.frame
+                        };
+                    }
+                }
+                proxy.version() < 3
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => true,
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                job_state.frame.y_invert = matches!(
+                    flags,
+                    WEnum::Value(f) if f.contains(zwlr_screencopy_frame_v1::Flags::YInvert)
+                );
+                false
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => {
+                proxy.destroy();
+                finish(&mut job_state, job.id, true, &state.ui_sender);
+                false
+            }
+            zwlr_screencopy_frame_v1::Event::Failed => {
+                proxy.destroy();
+                finish(&mut job_state, job.id, false, &state.ui_sender);
+                false
+            }
+            _ => false,
+        };
+
+        if copy_now && !job_state.started {
+            job_state.started = true;
+            let allocated = match state.shm.clone() {
+                Some(shm) => allocate(&mut job_state, &shm, qh),
+                None => false,
+            };
+            if !allocated {
+                proxy.destroy();
+                return finish(&mut job_state, job.id, false, &state.ui_sender);
+            }
+            proxy.copy(&job_state.buffer.as_ref().unwrap().buffer);
+        }
+    }
+}
+
+macro_rules! ignore_events {
+    ($($iface:ty),*) => {
+        $(
+            impl Dispatch<$iface, ()> for AppData {
+                fn event(
+                    _: &mut AppData,
+                    _: &$iface,
+                    _: <$iface as Proxy>::Event,
+                    _: &(),
+                    _: &Connection,
+                    _: &QueueHandle<AppData>,
+                ) {
+                }
+            }
+        )*
+    };
+}
+
+ignore_events!(
+    wl_shm::WlShm,
+    wl_shm_pool::WlShmPool,
+    wl_buffer::WlBuffer,
+    ExtImageCopyCaptureManagerV1,
+    ExtForeignToplevelImageCaptureSourceManagerV1,
+    ExtImageCaptureSourceV1,
+    ZwlrScreencopyManagerV1
+);
@@ -0,0 +1,105 @@
+use gio::prelude::*;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn new_provider(name: &str) -> gtk::CssProvider {
+    let provider = gtk::CssProvider::new();
+    let name = name.to_string();
+    provider.connect_parsing_error(move |_, section, error| {
+        eprintln!(
+            "[Style] {}:{}:{}: {}",
+            name,
+            section.start_line() + 1,
+            section.start_position() + 1,
+            error.message()
+        );
+    });
+    provider
+}
+
+fn add_to_screen(provider: &gtk::CssProvider, priority: u32) {
+    if let Some(screen) = gdk::Screen::default() {
+        gtk::StyleContext::add_provider_for_screen(&screen, provider, priority);
+    }
+}
+
+pub fn add_builtin(name: &str, css: &[u8]) {
+    let provider = new_provider(name);
+    if let Err(e) = provider.load_from_data(css) {
+        eprintln!("[Style] Failed to load built-in {}: {}", name, e);
+    }
+    add_to_screen(&provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+}
+
+pub struct UserStyle {
+    path: String,
+    provider: RefCell<Option<gtk::CssProvider>>,
+    monitor: RefCell<Option<gio::FileMonitor>>,
+}
+
+impl UserStyle {
+    pub fn default_path() -> String {
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        format!("{}/.config/labar/style.css", home)
+    }
+
+    pub fn watch(path: &str) -> Rc<Self> {
+        let style = Rc::new(UserStyle {
+            path: path.to_string(),
+            provider: RefCell::new(None),
+            monitor: RefCell::new(None),
+        });
+        style.reload();
+
+        let file = gio::File::for_path(path);
+        match file.monitor_file(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE) {
+            Ok(monitor) => {
+                let weak = Rc::downgrade(&style);
+                monitor.connect_changed(move |_, _, _, event| {
+                    let reload = matches!(
+                        event,
+                        gio::FileMonitorEvent::ChangesDoneHint
+                            | gio::FileMonitorEvent::Created
+                            | gio::FileMonitorEvent::Deleted
+                            | gio::FileMonitorEvent::MovedIn
+                            | gio::FileMonitorEvent::MovedOut
+                            | gio::FileMonitorEvent::Renamed
+                    );
+                    if reload {
+                        if let Some(style) = weak.upgrade() {
+                            style.reload();
+                        }
+                    }
+                });
+                *style.monitor.borrow_mut() = Some(monitor);
+            }
+            Err(e) => eprintln!("[Style] Cannot watch {}: {}", path, e),
+        }
+
+        style
+    }
+
+    // Builds a fresh provider and swaps it in, so a half-written file never
+    // leaves rules from the previous version stacked on top of the new ones.
+    pub fn reload(&self) {
+        if let Some(old) = self.provider.borrow_mut().take() {
+            if let Some(screen) = gdk::Screen::default() {
+                gtk::StyleContext::remove_provider_for_screen(&screen, &old);
+            }
+        }
+
+        let content = match std::fs::read(&self.path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+
+        let provider = new_provider(&self.path);
+        if let Err(e) = provider.load_from_data(&content) {
+            eprintln!("[Style] Failed to load {}: {}", self.path, e);
+        }
+        add_to_screen(&provider, gtk::STYLE_PROVIDER_PRIORITY_USER);
+        eprintln!("[Style] Loaded {}", self.path);
+        *self.provider.borrow_mut() = Some(provider);
+    }
+}
@@ -0,0 +1,124 @@
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Set for the panel processes the supervisor starts, so they run the panel
+// instead of supervising again.
+const SUPERVISED_ENV: &str = "LABAR_SUPERVISED";
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+// A panel that ran this long was connected, so the next delay starts over.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+// Consecutive short runs before giving up, about four minutes of retries.
+const MAX_FAILURES: u32 = 10;
+const POLL: Duration = Duration::from_millis(200);
+
+pub fn is_supervised() -> bool {
+    std::env::var_os(SUPERVISED_ENV).is_some()
+}
+
+// With `"reconnect": true` labar stays a small parent process that runs the
+// panel as a child and starts it again whenever it fails. GDK ends the process
+// itself when the compositor goes away, so the restart has to come from here.
+// Returns the exit status for labar.
+pub fn run() -> i32 {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        if let Err(e) = signal_hook::flag::register(signal, stop.clone()) {
+            eprintln!("[Supervisor] Cannot handle signal {}: {}", signal, e);
+        }
+    }
+
+    let mut delay = FIRST_DELAY;
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        let mut child = match spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("[Supervisor] Cannot start labar: {}", e);
+                return 1;
+            }
+        };
+        let Some(status) = wait(&mut child, &stop) else {
+            return 0;
+        };
+        if status.success() {
+            return 0;
+        }
+
+        if started.elapsed() >= STABLE_RUN {
+            delay = FIRST_DELAY;
+            failures = 0;
+        }
+        failures += 1;
+        if failures >= MAX_FAILURES {
+            eprintln!(
+                "[Supervisor] labar failed {} times in a row, giving up",
+                failures
+            );
+            return status.code().unwrap_or(1);
+        }
+
+        eprintln!(
+            "[Supervisor] labar exited ({}), restarting in {}s",
+            status,
+            delay.as_secs()
+        );
+        if !sleep(delay, &stop) {
+            return 0;
+        }
+        delay = (delay * 2).min(MAX_DELAY);
+    }
+}
+
+fn spawn() -> std::io::Result<Child> {
+    let exe = std::env::current_exe()?;
+    let mut command = Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(SUPERVISED_ENV, "1");
+    // The panel must not outlive a supervisor that is killed outright.
+    unsafe {
+        command.pre_exec(|| {
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+            Ok(())
+        });
+    }
+    command.spawn()
+}
+
+// Waits for the panel to exit. On SIGTERM or SIGINT the panel is asked to
+// stop the same way and None is returned.
+fn wait(child: &mut Child, stop: &AtomicBool) -> Option<ExitStatus> {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+            let _ = child.wait();
+            return None;
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) => thread::sleep(POLL),
+            Err(e) => {
+                eprintln!("[Supervisor] Failed to wait for labar: {}", e);
+                return Some(ExitStatus::from_raw(1 << 8));
+            }
+        }
+    }
+}
+
+// Returns false when a stop signal arrived during the delay.
+fn sleep(delay: Duration, stop: &AtomicBool) -> bool {
+    let until = Instant::now() + delay;
+    while Instant::now() < until {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        thread::sleep(POLL);
+    }
+    true
+}
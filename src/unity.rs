@@ -0,0 +1,254 @@
+use gio::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Count badges, progress and urgency that apps publish with the
+// com.canonical.Unity.LauncherEntry "Update" signal. Each signal only carries
+// the properties that changed, so the raw values are merged per app.
+#[derive(Clone, Debug, Default)]
+struct RawEntry {
+    sender: String,
+    count: i64,
+    count_visible: bool,
+    progress: f64,
+    progress_visible: bool,
+    urgent: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LauncherEntry {
+    pub count: Option<i64>,
+    pub progress: Option<f64>,
+    pub urgent: bool,
+}
+
+thread_local! {
+    // Keyed by desktop id, e.g. "org.telegram.desktop.desktop".
+    static ENTRIES: RefCell<HashMap<String, RawEntry>> = RefCell::new(HashMap::new());
+}
+
+fn desktop_id(app_id: &str) -> String {
+    crate::desktop::lookup(app_id)
+        .and_then(|info| info.id())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| format!("{}.desktop", app_id))
+}
+
+pub fn matches(app_id: &str, id: &str) -> bool {
+    desktop_id(app_id) == id
+}
+
+pub fn entry(app_id: &str) -> LauncherEntry {
+    entry_for(&desktop_id(app_id))
+}
+
+fn entry_for(id: &str) -> LauncherEntry {
+    ENTRIES.with(|entries| match entries.borrow().get(id) {
+        Some(raw) => LauncherEntry {
+            count: raw.count_visible.then_some(raw.count),
+            progress: raw.progress_visible.then_some(raw.progress.clamp(0.0, 1.0)),
+            urgent: raw.urgent,
+        },
+        None => LauncherEntry::default(),
+    })
+}
+
+fn update(sender: &str, params: &glib::Variant) -> Option<String> {
+    let uri = params.try_child_value(0)?.str()?.to_string();
+    let id = uri.strip_prefix("application://")?.to_string();
+    let props = glib::VariantDict::new(Some(&params.try_child_value(1)?));
+
+    ENTRIES.with(|entries| {
+        let mut entries = entries.borrow_mut();
+        let raw = entries.entry(id.clone()).or_default();
+        raw.sender = sender.to_string();
+        if let Ok(Some(count)) = props.lookup::<i64>("count") {
+            raw.count = count;
+        }
+        if let Ok(Some(visible)) = props.lookup::<bool>("count-visible") {
+            raw.count_visible = visible;
+        }
+        if let Ok(Some(progress)) = props.lookup::<f64>("progress") {
+            raw.progress = progress;
+        }
+        if let Ok(Some(visible)) = props.lookup::<bool>("progress-visible") {
+            raw.progress_visible = visible;
+        }
+        if let Ok(Some(urgent)) = props.lookup::<bool>("urgent") {
+            raw.urgent = urgent;
+        }
+    });
+    Some(id)
+}
+
+// Apps that quit without resetting their entry would otherwise keep their
+// badge forever.
+fn forget_sender(sender: &str) -> Vec<String> {
+    ENTRIES.with(|entries| {
+        let mut entries = entries.borrow_mut();
+        let gone: Vec<String> = entries
+            .iter()
+            .filter(|(_, raw)| raw.sender == sender)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in gone.iter() {
+            entries.remove(id);
+        }
+        gone
+    })
+}
+
+// Listens on the session bus; `on_change` gets the desktop id of every
+// entry that changed. Must be called on the GTK thread.
+pub fn watch(on_change: impl Fn(&str) + 'static) {
+    let conn = match gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("[Unity] No session bus, badges disabled: {}", e);
+            return;
+        }
+    };
+    let on_change = Rc::new(on_change);
+
+    let on_update = on_change.clone();
+    conn.signal_subscribe(
+        None,
+        Some("com.canonical.Unity.LauncherEntry"),
+        Some("Update"),
+        None,
+        None,
+        gio::DBusSignalFlags::NONE,
+        move |_, sender, _, _, _, params| {
+            if let Some(id) = update(sender, params) {
+                on_update(&id);
+            }
+        },
+    );
+
+    conn.signal_subscribe(
+        Some("org.freedesktop.DBus"),
+        Some("org.freedesktop.DBus"),
+        Some("NameOwnerChanged"),
+        Some("/org/freedesktop/DBus"),
+        None,
+        gio::DBusSignalFlags::NONE,
+        move |_, _, _, _, _, params| {
+            let Some((name, _, new_owner)) = params.get::<(String, String, String)>() else {
+                return;
+            };
+            if new_owner.is_empty() && name.starts_with(':') {
+                for id in forget_sender(&name) {
+                    on_change(&id);
+                }
+            }
+        },
+    );
+
+    // The subscriptions live as long as the connection, which is the shared
+    // session bus and stays open for the lifetime of the process.
+    std::mem::forget(conn);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(id: &str, props: &[(&str, glib::Variant)]) -> glib::Variant {
+        let dict = glib::VariantDict::new(None);
+        for (key, value) in props {
+            dict.insert_value(key, value);
+        }
+        let uri = format!("application://{}", id);
+        glib::Variant::tuple_from_iter([uri.to_variant(), dict.end()])
+    }
+
+    #[test]
+    fn merges_partial_updates() {
+        let id = "merge.desktop";
+        update(":1.1", &params(id, &[("count", 3i64.to_variant())]));
+        update(":1.1", &params(id, &[("count-visible", true.to_variant())]));
+        update(":1.1", &params(id, &[("urgent", true.to_variant())]));
+        assert_eq!(
+            entry_for(id),
+            LauncherEntry {
+                count: Some(3),
+                progress: None,
+                urgent: true,
+            }
+        );
+
+        update(":1.1", &params(id, &[("count", 5i64.to_variant())]));
+        assert_eq!(entry_for(id).count, Some(5));
+        assert!(entry_for(id).urgent);
+    }
+
+    #[test]
+    fn visible_flags_hide_values() {
+        let id = "visible.desktop";
+        let returned = update(
+            ":1.2",
+            &params(
+                id,
+                &[
+                    ("count", 7i64.to_variant()),
+                    ("progress", 1.5f64.to_variant()),
+                ],
+            ),
+        );
+        assert_eq!(returned.as_deref(), Some(id));
+        assert_eq!(entry_for(id), LauncherEntry::default());
+
+        update(
+            ":1.2",
+            &params(id, &[("progress-visible", true.to_variant())]),
+        );
+        assert_eq!(entry_for(id).count, None);
+        assert_eq!(entry_for(id).progress, Some(1.0));
+
+        update(
+            ":1.2",
+            &params(
+                id,
+                &[
+                    ("count-visible", true.to_variant()),
+                    ("progress-visible", false.to_variant()),
+                ],
+            ),
+        );
+        assert_eq!(entry_for(id).count, Some(7));
+        assert_eq!(entry_for(id).progress, None);
+    }
+
+    #[test]
+    fn ignores_other_uris() {
+        let params = glib::Variant::tuple_from_iter([
+            "file:///tmp/app".to_variant(),
+            glib::VariantDict::new(None).end(),
+        ]);
+        assert_eq!(update(":1.3", &params), None);
+    }
+
+    #[test]
+    fn forgets_entries_of_a_sender() {
+        update(
+            ":1.4",
+            &params("first.desktop", &[("urgent", true.to_variant())]),
+        );
+        update(
+            ":1.4",
+            &params("second.desktop", &[("urgent", true.to_variant())]),
+        );
+        update(
+            ":1.5",
+            &params("other.desktop", &[("urgent", true.to_variant())]),
+        );
+
+        let mut gone = forget_sender(":1.4");
+        gone.sort();
+        assert_eq!(gone, ["first.desktop", "second.desktop"]);
+        assert_eq!(entry_for("first.desktop"), LauncherEntry::default());
+        assert!(entry_for("other.desktop").urgent);
+        assert!(forget_sender(":1.4").is_empty());
+    }
+}
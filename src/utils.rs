@@ -1,84 +1,41 @@
-use std::io::{self, Read};
+use gtk::prelude::*;
+use gtk::Window;
+use gtk_layer_shell::LayerShell;
 use std::process::Command;
 
-pub fn fix_icon_name(id: &str) -> String {
-    let id_lower = id.to_lowercase();
+use crate::config::PanelEdge;
 
-    if id_lower.contains("firefox") {
-        return "firefox".to_string();
-    }
-    if id_lower.contains("chrome") {
-        return "google-chrome".to_string();
-    }
-    if id_lower.contains("chromium") {
-        return "chromium".to_string();
-    }
-    if id_lower.contains("zen") {
-        return "zen-browser".to_string();
-    }
-    if id_lower.contains("steam") {
-        return "steam".to_string();
-    }
-    if id_lower.contains("code") {
-        return "com.visualstudio.code".to_string();
-    }
-    if id_lower.contains("vscode") {
-        return "com.visualstudio.code".to_string();
-    }
-    if id_lower.contains("discord") {
-        return "discord".to_string();
-    }
-    if id_lower.contains("telegram") {
-        return "telegram".to_string();
-    }
-    if id_lower.contains("spotify") {
-        return "spotify".to_string();
-    }
-    if id_lower.contains("gimp") {
-        return "gimp".to_string();
-    }
-    if id_lower.contains("inkscape") {
-        return "inkscape".to_string();
-    }
-    if id_lower.contains("blender") {
-        return "blender".to_string();
-    }
-    if id_lower.contains("obs") {
-        return "com.obsproject.Studio".to_string();
-    }
-    if id_lower.contains("vlc") {
-        return "vlc".to_string();
-    }
-    if id_lower.contains("thunderbird") {
-        return "thunderbird".to_string();
-    }
-    if id_lower.contains("libreoffice") {
-        if id_lower.contains("writer") {
-            return "libreoffice-writer".to_string();
-        }
-        if id_lower.contains("calc") {
-            return "libreoffice-calc".to_string();
-        }
-        if id_lower.contains("impress") {
-            return "libreoffice-impress".to_string();
+// Anchors a popup against the panel edge. With `at_end` it also sticks to the
+// far end of the panel (right for horizontal bars, bottom for vertical ones),
+// otherwise it is centered along the edge. The popup opens on the monitor of
+// the button that triggered it.
+pub fn anchor_popup(
+    window: &Window,
+    edge: PanelEdge,
+    at_end: bool,
+    trigger: Option<&gtk::Widget>,
+) {
+    if let Some(gdk_window) = trigger.and_then(|t| t.window()) {
+        if let Some(monitor) = gdk_window.display().monitor_at_window(&gdk_window) {
+            window.set_monitor(&monitor);
         }
-        return "libreoffice-startcenter".to_string();
-    }
-    if id_lower.contains("files") || id_lower.contains("nautilus") {
-        return "system-file-manager".to_string();
-    }
-    if id_lower.contains("terminal")
-        || id_lower.contains("konsole")
-        || id_lower.contains("kitty")
-        || id_lower.contains("alacritty")
-    {
-        return "utilities-terminal".to_string();
-    }
-    if id_lower.contains("launcher") {
-        return "view-grid-symbolic".to_string();
     }
 
-    id.to_string()
+    let end_edge = if edge.is_vertical() {
+        gtk_layer_shell::Edge::Bottom
+    } else {
+        gtk_layer_shell::Edge::Right
+    };
+
+    for side in [
+        gtk_layer_shell::Edge::Top,
+        gtk_layer_shell::Edge::Bottom,
+        gtk_layer_shell::Edge::Left,
+        gtk_layer_shell::Edge::Right,
+    ] {
+        let anchored = side == edge.layer_edge() || (at_end && side == end_edge);
+        window.set_anchor(side, anchored);
+    }
 }
 
 pub fn exec_command(cmd: &str) -> String {
@@ -3,14 +3,24 @@ use wayland_client::{Connection, Dispatch, Proxy, QueueHandle};
 use std::sync::{Arc, Mutex};
 use std::thread;
 pub use wayland_client;
-use wayland_client::globals::GlobalListContents;
-pub use wayland_client::protocol::{wl_keyboard, wl_output, wl_registry, wl_seat, wl_surface};
-use wayland_client::EventQueue;
+pub use wayland_client::protocol::{wl_keyboard, wl_output, wl_registry, wl_seat, wl_shm, wl_surface};
+use wayland_backend::client::{Backend, ObjectId};
+use gdk::prelude::MonitorExt;
+use glib::prelude::*;
 
 use memmap2::MmapOptions;
-use std::os::unix::io::FromRawFd;
 use xkbcommon::xkb;
 
+use crate::ext_toplevels::{self, ExtToplevel};
+use crate::keyboard::{self, Keyboard, Locks};
+use crate::screencopy::{Capturer, Thumbnail};
+use crate::workspaces::Workspaces;
+
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+};
+
 pub use wayland_protocols_wlr::foreign_toplevel::v1::client as wlr_foreign_toplevel;
 
 use wlr_foreign_toplevel::{
@@ -18,48 +28,222 @@ use wlr_foreign_toplevel::{
     zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
 };
 
-#[derive(Clone, Debug)]
+// Assigned by labar when the toplevel is announced; never reused, unlike the
+// protocol object id.
+pub type ToplevelId = u64;
+
+// Which properties of a toplevel changed since its last `done`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToplevelFields {
+    pub title: bool,
+    pub app_id: bool,
+    pub state: bool,
+    // The window gained focus in this batch.
+    pub focused: bool,
+    pub outputs: bool,
+}
+
+impl ToplevelFields {
+    pub fn is_empty(&self) -> bool {
+        *self == ToplevelFields::default()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct WindowHandle {
-    pub id: String,
+    pub id: ToplevelId,
     pub title: String,
     pub app_id: String,
     pub minimized: bool,
     pub activated: bool,
-    pub handle: ZwlrForeignToplevelHandleV1,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    pub outputs: Vec<wl_output::WlOutput>,
+    // Requests go through wlr; a window known only from the ext list is
+    // read-only.
+    pub wlr: Option<ZwlrForeignToplevelHandleV1>,
+    pub ext: Option<ExtForeignToplevelHandleV1>,
+    // The ext-foreign-toplevel-list identifier, stable for the window's life.
+    pub identifier: Option<String>,
+    // Set after the first `done`, so the UI and subscribers see a window once
+    // its title and app_id are known.
+    pub announced: bool,
+    pub pending: ToplevelFields,
 }
 
 impl WindowHandle {
+    pub fn state(&self) -> ToplevelState {
+        ToplevelState {
+            maximized: self.maximized,
+            minimized: self.minimized,
+            activated: self.activated,
+            fullscreen: self.fullscreen,
+        }
+    }
+
+    // Whether the window can be minimized, activated, closed and so on.
+    pub fn can_control(&self) -> bool {
+        self.wlr.is_some()
+    }
+
     pub fn set_minimized(&self) {
-        self.handle.set_minimized();
+        if let Some(handle) = &self.wlr {
+            handle.set_minimized();
+        }
     }
 
     pub fn unset_minimized(&self) {
-        self.handle.unset_minimized();
+        if let Some(handle) = &self.wlr {
+            handle.unset_minimized();
+        }
+    }
+
+    pub fn set_maximized(&self) {
+        if let Some(handle) = &self.wlr {
+            handle.set_maximized();
+        }
+    }
+
+    pub fn unset_maximized(&self) {
+        if let Some(handle) = &self.wlr {
+            handle.unset_maximized();
+        }
+    }
+
+    // Fullscreen requests only exist from version 2 of the protocol.
+    pub fn can_fullscreen(&self) -> bool {
+        self.wlr.as_ref().is_some_and(|handle| handle.version() >= 2)
+    }
+
+    pub fn set_fullscreen(&self) {
+        if let Some(handle) = self.wlr.as_ref().filter(|_| self.can_fullscreen()) {
+            handle.set_fullscreen(None);
+        }
+    }
+
+    pub fn unset_fullscreen(&self) {
+        if let Some(handle) = self.wlr.as_ref().filter(|_| self.can_fullscreen()) {
+            handle.unset_fullscreen();
+        }
     }
 
     pub fn activate(&self, seat: &wl_seat::WlSeat) {
-        self.handle.activate(seat);
+        if let Some(handle) = &self.wlr {
+            handle.activate(seat);
+        }
     }
 
     pub fn close(&self) {
-        self.handle.close();
+        if let Some(handle) = &self.wlr {
+            handle.close();
+        }
+    }
+
+    pub fn set_rectangle(&self, surface: &wl_surface::WlSurface, x: i32, y: i32, w: i32, h: i32) {
+        if let Some(handle) = &self.wlr {
+            handle.set_rectangle(surface, x, y, w, h);
+        }
     }
 }
 
+fn gdk_wl_display(display: &gdk::Display) -> Option<*mut std::ffi::c_void> {
+    if display.type_().name() != "GdkWaylandDisplay" {
+        return None;
+    }
+    let ptr = unsafe {
+        gdk_wayland_sys::gdk_wayland_display_get_wl_display(display.as_ptr() as *mut _)
+    };
+    (!ptr.is_null()).then_some(ptr)
+}
+
+// Using GTK's own wl_display lets us name the panel surfaces in requests
+// such as set_rectangle, which a second connection could not do.
+fn gtk_connection() -> Option<Connection> {
+    let display = gdk::Display::default()?;
+    let ptr = gdk_wl_display(&display)?;
+    let backend = unsafe { Backend::from_foreign_display(ptr as *mut _) };
+    Some(Connection::from_backend(backend))
+}
+
+// The wl_surface behind a realized GTK window, if it lives on our connection.
+pub fn gtk_surface(conn: &Connection, window: &gdk::Window) -> Option<wl_surface::WlSurface> {
+    let display = gdk_wl_display(&window.display())?;
+    if conn.backend().display_ptr() as *mut std::ffi::c_void != display {
+        return None;
+    }
+    let ptr = unsafe {
+        gdk_wayland_sys::gdk_wayland_window_get_wl_surface(window.as_ptr() as *mut _)
+    };
+    if ptr.is_null() {
+        return None;
+    }
+    let id =
+        unsafe { ObjectId::from_ptr(wl_surface::WlSurface::interface(), ptr as *mut _) }.ok()?;
+    wl_surface::WlSurface::from_id(conn, id).ok()
+}
+
+// GDK's wl_output for a monitor. The compositor sends output events to every
+// wl_output the client bound, so toplevels and workspaces name this one too.
+pub fn gtk_output(conn: &Connection, monitor: &gdk::Monitor) -> Option<wl_output::WlOutput> {
+    let display = gdk_wl_display(&monitor.display()?)?;
+    if conn.backend().display_ptr() as *mut std::ffi::c_void != display {
+        return None;
+    }
+    let ptr = unsafe {
+        gdk_wayland_sys::gdk_wayland_monitor_get_wl_output(monitor.as_ptr() as *mut _)
+    };
+    if ptr.is_null() {
+        return None;
+    }
+    let id =
+        unsafe { ObjectId::from_ptr(wl_output::WlOutput::interface(), ptr as *mut _) }.ok()?;
+    wl_output::WlOutput::from_id(conn, id).ok()
+}
+
+// What the compositor lets labar do, so modules can explain themselves
+// instead of staying empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    // Windows can be listed, through either toplevel protocol.
+    pub toplevels: bool,
+    // wlr-foreign-toplevel-management: windows can also be controlled.
+    pub window_control: bool,
+    // ext-workspace-v1.
+    pub workspaces: bool,
+    // A wl_seat, needed to activate windows.
+    pub seat: bool,
+}
+
 #[derive(Debug, Clone)]
 pub enum UiEvent {
     Refresh,
-    KeyboardLayout(String),
+    Capabilities(Capabilities),
+    // A batch of ext-workspace changes is complete.
+    Workspaces,
+    // The Wayland connection failed; carries the error.
+    Disconnected(String),
+    // The keymap or the Caps/Num/Scroll Lock state changed.
+    Keyboard,
+    ToplevelNew(ToplevelId),
+    ToplevelChanged {
+        id: ToplevelId,
+        fields: ToplevelFields,
+    },
+    ToplevelClosed(ToplevelId),
+    Thumbnail(ToplevelId, Option<Thumbnail>),
+    // Sent after each batch of toplevel events, once the window list is consistent.
+    Done,
 }
 
 #[derive(Debug)]
 pub struct WaylandClient {
     pub conn: Connection,
-    pub(crate) event_queue: Arc<Mutex<EventQueue<AppData>>>,
-    pub(crate) qh: QueueHandle<AppData>,
     pub windows: Arc<Mutex<Vec<WindowHandle>>>,
     pub seat: Arc<Mutex<Option<wl_seat::WlSeat>>>,
-    pub keyboard_layout: Arc<Mutex<String>>,
+    pub keyboard: Arc<Mutex<Keyboard>>,
+    pub capturer: Arc<Capturer>,
+    pub workspaces: Arc<Mutex<Workspaces>>,
+    pub capabilities: Capabilities,
 }
 
 pub struct SendXkbContext(pub xkb::Context);
@@ -71,27 +255,38 @@ unsafe impl Send for SendXkbState {}
 pub struct AppData {
     pub windows: Arc<Mutex<Vec<WindowHandle>>>,
     pub manager: Option<ZwlrForeignToplevelManagerV1>,
-    pub seat: Option<wl_seat::WlSeat>,
-    pub keyboard_layout: Arc<Mutex<String>>,
     pub ui_sender: glib::Sender<UiEvent>,
     pub xkb_context: SendXkbContext,
     pub xkb_state: Option<SendXkbState>,
-    pub layout_names: Vec<String>,
+    pub keyboard: Arc<Mutex<Keyboard>>,
+    pub next_toplevel_id: ToplevelId,
+    pub shm: Option<wl_shm::WlShm>,
+    pub ext_list: Option<ExtForeignToplevelListV1>,
+    pub ext_toplevels: Vec<ExtToplevel>,
+    pub workspaces: Arc<Mutex<Workspaces>>,
+    pub capabilities: Capabilities,
 }
 
 impl WaylandClient {
-    pub fn new(ui_sender: glib::Sender<UiEvent>) -> Self {
+    pub fn new(ui_sender: glib::Sender<UiEvent>) -> Result<Self, String> {
         eprintln!("[Wayland] Connecting...");
-        let conn = Connection::connect_to_env().expect("Failed to connect to Wayland");
+        let conn = match gtk_connection() {
+            Some(conn) => conn,
+            None => {
+                eprintln!("[Wayland] GDK is not using Wayland, opening a separate connection");
+                Connection::connect_to_env()
+                    .map_err(|e| format!("cannot connect to Wayland: {}", e))?
+            }
+        };
         eprintln!("[Wayland] Connected. Initializing Registry...");
 
-        let (globals, event_queue) = wayland_client::globals::registry_queue_init::<AppData>(&conn)
-            .expect("Failed registry init");
+        let (globals, mut event_queue) =
+            wayland_client::globals::registry_queue_init::<AppData>(&conn)
+            .map_err(|e| format!("registry init failed: {}", e))?;
         let qh = event_queue.handle();
 
         let windows = Arc::new(Mutex::new(Vec::new()));
         let seat = Arc::new(Mutex::new(None));
-        let keyboard_layout = Arc::new(Mutex::new("US".to_string()));
 
         let seat_clone = seat.clone();
 
@@ -100,12 +295,16 @@ impl WaylandClient {
         let mut app_data = AppData {
             windows: windows.clone(),
             manager: None,
-            seat: None,
-            keyboard_layout: keyboard_layout.clone(),
             ui_sender,
             xkb_context: SendXkbContext(xkb_context),
             xkb_state: None,
-            layout_names: Vec::new(),
+            keyboard: Arc::new(Mutex::new(Keyboard::default())),
+            next_toplevel_id: 1,
+            shm: None,
+            ext_list: None,
+            ext_toplevels: Vec::new(),
+            workspaces: Arc::new(Mutex::new(Workspaces::default())),
+            capabilities: Capabilities::default(),
         };
 
         eprintln!("[Wayland] Binding Globals...");
@@ -113,61 +312,149 @@ impl WaylandClient {
             Ok(manager) => {
                 eprintln!("[Wayland] Bound Foreign Toplevel Manager successfully.");
                 app_data.manager = Some(manager);
+                app_data.capabilities.window_control = true;
             }
-            Err(e) => eprintln!("[Wayland] FAILED to bind Foreign Toplevel Manager: {:?}", e),
+            Err(e) => eprintln!("[Wayland] No wlr-foreign-toplevel-management: {:?}", e),
+        }
+
+        // Bound after the wlr manager: whether it exists decides how ext
+        // toplevels are used.
+        app_data.ext_list = ext_toplevels::bind(&globals, &qh);
+        app_data.capabilities.toplevels =
+            app_data.manager.is_some() || app_data.ext_list.is_some();
+        match (app_data.manager.is_some(), app_data.ext_list.is_some()) {
+            (true, true) => eprintln!("[Wayland] Toplevels: wlr, with ext identifiers"),
+            (true, false) => eprintln!("[Wayland] Toplevels: wlr"),
+            (false, true) => eprintln!("[Wayland] Toplevels: ext list, read-only"),
+            (false, false) => eprintln!("[Wayland] No toplevel protocol, the taskbar stays empty"),
         }
 
         match globals.bind::<wl_seat::WlSeat, _, _>(&qh, 1..=1, ()) {
             Ok(s) => {
                 eprintln!("[Wayland] Bound WlSeat.");
                 *seat_clone.lock().unwrap() = Some(s);
+                app_data.capabilities.seat = true;
             }
             Err(e) => eprintln!("[Wayland] FAILED to bind WlSeat: {:?}", e),
         }
 
-        let event_queue_arc = Arc::new(Mutex::new(event_queue));
-        let event_queue_clone = event_queue_arc.clone();
+        let capturer = Arc::new(Capturer::bind(&globals, &conn, &qh));
+        app_data.shm = capturer.shm.clone();
+
+        let workspaces = Workspaces::bind(&globals, &qh);
+        app_data.capabilities.workspaces = workspaces.manager.is_some();
+        *app_data.workspaces.lock().unwrap() = workspaces;
+        let workspaces = app_data.workspaces.clone();
+
+        // Pick up the initial toplevel list before any panel asks for it.
+        if let Err(e) = event_queue.roundtrip(&mut app_data) {
+            eprintln!("[Wayland] Initial roundtrip failed: {:?}", e);
+        }
+
+        let capabilities = app_data.capabilities;
+        let keyboard = app_data.keyboard.clone();
 
         thread::spawn(move || {
-            let mut event_queue_locked = event_queue_clone.lock().unwrap();
             eprintln!("[Wayland] Event Loop started.");
             loop {
-                if let Err(e) = event_queue_locked.blocking_dispatch(&mut app_data) {
+                if let Err(e) = event_queue.blocking_dispatch(&mut app_data) {
                     eprintln!("[Wayland] Dispatch error: {:?}", e);
+                    app_data.ui_sender.send(UiEvent::Disconnected(e.to_string())).ok();
                     break;
                 }
             }
         });
 
-        WaylandClient {
+        Ok(WaylandClient {
             conn,
-            event_queue: event_queue_arc,
-            qh,
             windows,
             seat,
-            keyboard_layout,
+            keyboard,
+            capturer,
+            workspaces,
+            capabilities,
+        })
+    }
+}
+
+// Sends the changes batched up to a toplevel's `done`, shared by both
+// toplevel protocols.
+pub(crate) fn report_done(ui_sender: &glib::Sender<UiEvent>, win: &mut WindowHandle) {
+    let fields = std::mem::take(&mut win.pending);
+    if !win.announced {
+        win.announced = true;
+        crate::ipc::publish(crate::ipc::Event::WindowNew {
+            id: win.id,
+            title: win.title.clone(),
+            app_id: win.app_id.clone(),
+        });
+        ui_sender.send(UiEvent::ToplevelNew(win.id)).ok();
+    } else if !fields.is_empty() {
+        if fields.title {
+            crate::ipc::publish(crate::ipc::Event::WindowTitle {
+                id: win.id,
+                title: win.title.clone(),
+            });
         }
+        ui_sender
+            .send(UiEvent::ToplevelChanged { id: win.id, fields })
+            .ok();
+    } else {
+        return;
     }
+    if fields.focused {
+        crate::ipc::publish(crate::ipc::Event::WindowActivated {
+            id: win.id,
+            app_id: win.app_id.clone(),
+        });
+    }
+    ui_sender.send(UiEvent::Done).ok();
 }
 
-fn parse_state(state_bytes: &[u8]) -> (bool, bool) {
-    let mut activated = false;
-    let mut minimized = false;
+pub(crate) fn report_closed(ui_sender: &glib::Sender<UiEvent>, win: WindowHandle) {
+    eprintln!("[Wayland] Window Closed: {}", win.id);
+    if win.announced {
+        crate::ipc::publish(crate::ipc::Event::WindowClosed { id: win.id });
+        ui_sender.send(UiEvent::ToplevelClosed(win.id)).ok();
+        ui_sender.send(UiEvent::Done).ok();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToplevelState {
+    pub maximized: bool,
+    pub minimized: bool,
+    pub activated: bool,
+    pub fullscreen: bool,
+}
+
+// IPC subscribers see the short code the keyboard module shows.
+fn publish_layout(keyboard: &Keyboard) {
+    if let Some(layout) = keyboard.current() {
+        crate::ipc::publish(crate::ipc::Event::KeyboardLayout {
+            layout: layout.short.clone(),
+        });
+    }
+}
+
+fn parse_state(state_bytes: &[u8]) -> ToplevelState {
+    let mut state = ToplevelState::default();
 
     for chunk in state_bytes.chunks(4) {
         if chunk.len() == 4 {
             let val = u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
 
-            if val == 2 {
-                activated = true;
-            }
-            if val == 1 {
-                minimized = true;
+            match val {
+                0 => state.maximized = true,
+                1 => state.minimized = true,
+                2 => state.activated = true,
+                3 => state.fullscreen = true,
+                _ => {}
             }
         }
     }
 
-    (activated, minimized)
+    state
 }
 
 impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppData {
@@ -181,17 +468,23 @@ impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppData {
     ) {
         match event {
             zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
-                let id = format!("{:?}", toplevel.id());
+                let id = state.next_toplevel_id;
+                state.next_toplevel_id += 1;
                 eprintln!("[Wayland] New toplevel found: ID={}", id);
                 state.windows.lock().unwrap().push(WindowHandle {
                     id,
-                    title: "".into(),
-                    app_id: "".into(),
-                    minimized: false,
-                    activated: false,
-                    handle: toplevel,
+                    wlr: Some(toplevel),
+                    ..WindowHandle::default()
                 });
-                state.ui_sender.send(UiEvent::Refresh).ok();
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                eprintln!("[Wayland] Foreign Toplevel Manager finished, windows are read-only now");
+                state.manager = None;
+                // The ext list, if bound, keeps the taskbar going without controls.
+                state.capabilities.toplevels = state.ext_list.is_some();
+                state.capabilities.window_control = false;
+                ext_toplevels::take_over(state);
+                state.ui_sender.send(UiEvent::Capabilities(state.capabilities)).ok();
             }
             _ => {}
         }
@@ -199,19 +492,36 @@ impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppData {
 
     fn event_created_child(
         opcode: u16,
-        _qh: &QueueHandle<AppData>,
-    ) -> std::sync::Arc<dyn wayland_client::backend::ObjectData + 'static> {
-        if opcode == 0 {
-            // Opcode 0 is the `toplevel` event which creates a ZwlrForeignToplevelHandleV1
-            // We return the ObjectData created by the queue handle for this interface and user data
-            _qh.make_data::<ZwlrForeignToplevelHandleV1, ()>(())
+        qh: &QueueHandle<AppData>,
+    ) -> Arc<dyn wayland_client::backend::ObjectData + 'static> {
+        if opcode == zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE {
+            qh.make_data::<ZwlrForeignToplevelHandleV1, ()>(())
         } else {
-            // This should not happen for this interface
-            panic!("Unknown opcode causing child creation")
+            eprintln!(
+                "[Wayland] Unexpected object from foreign toplevel manager, opcode {}",
+                opcode
+            );
+            Arc::new(IgnoredObject)
         }
     }
 }
 
+// Object data for objects labar did not expect to be created, e.g. by a newer
+// protocol version. Their events are dropped instead of aborting the client.
+struct IgnoredObject;
+
+impl wayland_client::backend::ObjectData for IgnoredObject {
+    fn event(
+        self: Arc<Self>,
+        _backend: &Backend,
+        _msg: wayland_backend::protocol::Message<ObjectId, std::os::fd::OwnedFd>,
+    ) -> Option<Arc<dyn wayland_client::backend::ObjectData>> {
+        None
+    }
+
+    fn destroyed(&self, _object_id: ObjectId) {}
+}
+
 impl Dispatch<wl_seat::WlSeat, ()> for AppData {
     fn event(
         _state: &mut AppData,
@@ -221,12 +531,13 @@ impl Dispatch<wl_seat::WlSeat, ()> for AppData {
         _: &Connection,
         qh: &QueueHandle<AppData>,
     ) {
-        if let wl_seat::Event::Capabilities { capabilities } = event {
-            if let wayland_client::WEnum::Value(caps) = capabilities {
-                if caps.contains(wl_seat::Capability::Keyboard) {
-                    eprintln!("[Wayland] Seat has keyboard, getting keyboard...");
-                    seat.get_keyboard(qh, ());
-                }
+        if let wl_seat::Event::Capabilities {
+            capabilities: wayland_client::WEnum::Value(caps),
+        } = event
+        {
+            if caps.contains(wl_seat::Capability::Keyboard) {
+                eprintln!("[Wayland] Seat has keyboard, getting keyboard...");
+                seat.get_keyboard(qh, ());
             }
         }
     }
@@ -242,95 +553,55 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for AppData {
         _: &QueueHandle<AppData>,
     ) {
         match event {
-            wl_keyboard::Event::Keymap { format, fd, size } => {
-                if format == wayland_client::WEnum::Value(wl_keyboard::KeymapFormat::XkbV1) {
-                    eprintln!("[Wayland] Keymap received. Size: {}", size);
-
-                    // fd is OwnedFd, convert to File to use with Mmap
-                    let file = std::fs::File::from(fd);
-
-                    let keymap_string = unsafe {
-                        let mmap = MmapOptions::new()
-                            .len(size as usize)
-                            .map(&file)
-                            .expect("Failed to mmap keymap");
-                        String::from_utf8_lossy(&mmap[..size as usize - 1]).into_owned()
-                        // remove null terminator?
-                    };
-
-                    eprintln!(
-                        "[Wayland] Keymap Header: {}",
-                        &keymap_string.chars().take(500).collect::<String>()
-                    );
-
-                    // access context from wrapper
-                    if let Some(keymap) = xkb::Keymap::new_from_string(
-                        &state.xkb_context.0,
-                        keymap_string.clone(),
-                        xkb::KEYMAP_FORMAT_TEXT_V1,
-                        xkb::KEYMAP_COMPILE_NO_FLAGS,
-                    ) {
-                        // Extract layout names
-                        state.layout_names.clear();
-
-                        // Try to parse short codes from "xkb_symbols { include "..." }"
-                        let mut short_codes = Vec::new();
-                        if let Some(start) = keymap_string.find("xkb_symbols") {
-                            if let Some(include_start) = keymap_string[start..].find("include") {
-                                // keymap_string[start + include_start..] starts with "include"
-                                let substr = &keymap_string[start + include_start..];
-                                if let Some(quote_start) = substr.find('"') {
-                                    if let Some(quote_end) = substr[quote_start + 1..].find('"') {
-                                        let include_str =
-                                            &substr[quote_start + 1..quote_start + 1 + quote_end];
-                                        eprintln!(
-                                            "[Wayland] Found include string: {}",
-                                            include_str
-                                        );
-
-                                        // Parse include string: "pc+us+ru:2+inet(evdev)"
-                                        for part in include_str.split('+') {
-                                            // Remove variant (e.g. "ru(winkeys)" -> "ru")
-                                            let code = part.split('(').next().unwrap_or("").trim();
-                                            // Handle group index (e.g. "ru:2" -> "ru")
-                                            let code = code.split(':').next().unwrap_or("").trim();
-
-                                            match code {
-                                                "pc" | "evdev" | "inet" | "base" | "aliases"
-                                                | "empty" | "complete" => continue,
-                                                _ => {
-                                                    if !code.is_empty() {
-                                                        short_codes.push(code.to_uppercase());
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-
-                        eprintln!("[Wayland] Parsed short codes: {:?}", short_codes);
-
-                        let num_layouts = keymap.num_layouts();
-                        for i in 0..num_layouts {
-                            if i < short_codes.len() as u32 {
-                                state.layout_names.push(short_codes[i as usize].clone());
-                            } else {
-                                let name = keymap.layout_get_name(i).to_string();
-                                let short_name = &name[0..std::cmp::min(2, name.len())];
-                                state.layout_names.push(short_name.to_uppercase());
-                            }
-                        }
-
-                        state.xkb_state = Some(SendXkbState(xkb::State::new(&keymap)));
+            wl_keyboard::Event::Keymap {
+                format: wayland_client::WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => {
+                eprintln!("[Wayland] Keymap received. Size: {}", size);
+
+                // fd is OwnedFd, convert to File to use with Mmap
+                let file = std::fs::File::from(fd);
+
+                let keymap_string = unsafe {
+                    let mmap = MmapOptions::new()
+                        .len(size as usize)
+                        .map(&file)
+                        .expect("Failed to mmap keymap");
+                    String::from_utf8_lossy(&mmap[..size as usize - 1]).into_owned()
+                    // remove null terminator?
+                };
+
+                eprintln!(
+                    "[Wayland] Keymap Header: {}",
+                    &keymap_string.chars().take(500).collect::<String>()
+                );
+
+                // access context from wrapper
+                if let Some(keymap) = xkb::Keymap::new_from_string(
+                    &state.xkb_context.0,
+                    keymap_string.clone(),
+                    xkb::KEYMAP_FORMAT_TEXT_V1,
+                    xkb::KEYMAP_COMPILE_NO_FLAGS,
+                ) {
+                    let layouts = keyboard::layouts(&keymap, &keymap_string);
+
+                    let xkb_state = xkb::State::new(&keymap);
+                    {
+                        let mut keyboard = state.keyboard.lock().unwrap();
+                        keyboard.layouts = layouts;
+                        keyboard.current = 0;
+                        keyboard.locks = Locks::from_state(&xkb_state);
                         eprintln!(
                             "[Wayland] XKB State created. Layouts: {:?}",
-                            state.layout_names
+                            keyboard.layouts
                         );
-                    } else {
-                        eprintln!("[Wayland] Failed to compile keymap");
                     }
+                    state.xkb_state = Some(SendXkbState(xkb_state));
+                    state.ui_sender.send(UiEvent::Keyboard).ok();
+                    publish_layout(&state.keyboard.lock().unwrap());
+                } else {
+                    eprintln!("[Wayland] Failed to compile keymap");
                 }
             }
             wl_keyboard::Event::Modifiers {
@@ -346,18 +617,22 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for AppData {
                     xkb_state.update_mask(mods_depressed, mods_latched, mods_locked, 0, 0, group);
 
                     let layout_idx = group as usize;
-
-                    let layout_name = if layout_idx < state.layout_names.len() {
-                        state.layout_names[layout_idx].clone()
-                    } else {
-                        "??".to_string()
+                    let locks = Locks::from_state(xkb_state);
+
+                    let (layout_changed, keyboard_changed) = {
+                        let mut keyboard = state.keyboard.lock().unwrap();
+                        let layout_changed = keyboard.current != layout_idx;
+                        let changed = layout_changed || keyboard.locks != locks;
+                        keyboard.current = layout_idx;
+                        keyboard.locks = locks;
+                        (layout_changed, changed)
                     };
-
-                    *state.keyboard_layout.lock().unwrap() = layout_name.clone();
-                    state
-                        .ui_sender
-                        .send(UiEvent::KeyboardLayout(layout_name))
-                        .ok();
+                    if keyboard_changed {
+                        state.ui_sender.send(UiEvent::Keyboard).ok();
+                    }
+                    if layout_changed {
+                        publish_layout(&state.keyboard.lock().unwrap());
+                    }
                 }
             }
             _ => {}
@@ -375,35 +650,60 @@ impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for AppData {
         _qh: &QueueHandle<AppData>,
     ) {
         let mut windows = state.windows.lock().unwrap();
-        let id = format!("{:?}", proxy.id());
-        if let Some(win) = windows.iter_mut().find(|w| w.id == id) {
-            match event {
-                zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
-                    win.title = title;
-                    state.ui_sender.send(UiEvent::Refresh).ok();
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
-                    eprintln!("[Wayland] Update AppId: {} -> {}", id, app_id);
-                    win.app_id = app_id;
-                    state.ui_sender.send(UiEvent::Refresh).ok();
-                }
-                zwlr_foreign_toplevel_handle_v1::Event::State { state: state_bytes } => {
-                    let (activated, minimized) = parse_state(&state_bytes);
+        let Some(index) = windows.iter().position(|w| w.wlr.as_ref() == Some(proxy)) else {
+            return;
+        };
+
+        if let zwlr_foreign_toplevel_handle_v1::Event::Closed = event {
+            report_closed(&state.ui_sender, windows.remove(index));
+            proxy.destroy();
+            return;
+        }
 
-                    win.activated = activated;
-                    win.minimized = minimized;
-                    state.ui_sender.send(UiEvent::Refresh).ok();
+        let win = &mut windows[index];
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } if win.title != title => {
+                win.title = title;
+                win.pending.title = true;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } if win.app_id != app_id => {
+                eprintln!("[Wayland] Update AppId: {} -> {}", win.id, app_id);
+                win.app_id = app_id;
+                win.pending.app_id = true;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: state_bytes } => {
+                let new_state = parse_state(&state_bytes);
+                if new_state.activated && !win.activated {
+                    win.pending.focused = true;
                 }
-                zwlr_foreign_toplevel_handle_v1::Event::Closed => {
-                    eprintln!("[Wayland] Window Closed: {}", id);
-                    win.id = "CLOSED".to_string();
-                    state.ui_sender.send(UiEvent::Refresh).ok();
+                if win.state() != new_state {
+                    win.activated = new_state.activated;
+                    win.minimized = new_state.minimized;
+                    win.maximized = new_state.maximized;
+                    win.fullscreen = new_state.fullscreen;
+                    win.pending.state = true;
                 }
-                _ => {}
             }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output }
+                if !win.outputs.contains(&output) =>
+            {
+                win.outputs.push(output);
+                win.pending.outputs = true;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                win.outputs.retain(|o| o != &output);
+                win.pending.outputs = true;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                let relink = win.ext.is_none() && (win.pending.app_id || win.pending.title);
+                report_done(&state.ui_sender, win);
+                drop(windows);
+                if relink {
+                    ext_toplevels::relink(state);
+                }
+            }
+            _ => {}
         }
-
-        windows.retain(|w| w.id != "CLOSED");
     }
 }
 
@@ -1,21 +1,19 @@
+use crate::config::PanelEdge;
 use crate::utils::exec_command;
-use glib;
 use gtk::prelude::*;
 use gtk::{
     Box, Button, Dialog, DialogFlags, Entry, Image, Label, Orientation, ResponseType,
     ScrolledWindow, Switch, Window, WindowType,
 };
 use gtk_layer_shell::LayerShell;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::process::Command;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
 struct WiFiNetwork {
     ssid: String,
-    bssid: String,
     signal: i32,
     secured: bool,
     connected: bool,
@@ -25,10 +23,10 @@ struct WiFiNetwork {
 pub struct WiFiPopup {
     window: Window,
     backdrop: Window,
-    main_box: Box,
     networks_list: Box,
     wifi_switch: Switch,
-    trigger_button: Arc<Mutex<Option<gtk::Widget>>>,
+    trigger_button: RefCell<Option<gtk::Widget>>,
+    panel_edge: Cell<PanelEdge>,
     networks: Rc<RefCell<Vec<WiFiNetwork>>>,
     wifi_enabled: Rc<RefCell<bool>>,
 }
@@ -45,14 +43,9 @@ impl WiFiPopup {
         backdrop.set_decorated(false);
         backdrop.set_widget_name("backdrop-window");
 
-        let backdrop_css = gtk::CssProvider::new();
-        backdrop_css
-            .load_from_data(b"#backdrop-window { background: transparent; }")
-            .ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &backdrop_css,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        crate::style::add_builtin(
+            "backdrop",
+            b"#backdrop-window { background: transparent; }",
         );
 
         let window = Window::new(WindowType::Toplevel);
@@ -112,10 +105,10 @@ impl WiFiPopup {
         let instance = WiFiPopup {
             window,
             backdrop,
-            main_box,
             networks_list,
             wifi_switch,
-            trigger_button: Arc::new(Mutex::new(None)),
+            trigger_button: RefCell::new(None),
+            panel_edge: Cell::new(PanelEdge::Bottom),
             networks: Rc::new(RefCell::new(Vec::new())),
             wifi_enabled: Rc::new(RefCell::new(true)),
         };
@@ -128,13 +121,7 @@ impl WiFiPopup {
             glib::Propagation::Stop
         });
 
-        let provider = gtk::CssProvider::new();
-        provider.load_from_data(include_bytes!("wifi.css")).ok();
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().unwrap(),
-            &provider,
-            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
-        );
+        crate::style::add_builtin("wifi.css", include_bytes!("wifi.css"));
 
         let wifi_enabled_clone = instance.wifi_enabled.clone();
         let networks_list_clone = instance.networks_list.clone();
@@ -259,7 +246,6 @@ impl WiFiPopup {
             }
             seen_ssids.insert(ssid.clone());
 
-            let bssid = parts[1].clone();
             let signal: i32 = parts[2].parse().unwrap_or(50);
             let security = parts[3].clone();
 
@@ -269,7 +255,6 @@ impl WiFiPopup {
 
             nets.push(WiFiNetwork {
                 ssid,
-                bssid,
                 signal,
                 secured: is_secured,
                 connected: is_connected,
@@ -420,7 +405,7 @@ impl WiFiPopup {
         let networks_c = networks.clone();
         let wifi_enabled_c = wifi_enabled.clone();
 
-        connect_btn.connect_clicked(move |btn| {
+        connect_btn.connect_clicked(move |_| {
             if connected {
                 let cmd = format!("nmcli connection down \"{}\"", ssid);
                 let _ = Command::new("sh").arg("-c").arg(&cmd).status();
@@ -473,10 +458,9 @@ impl WiFiPopup {
         content.set_margin_bottom(20);
         content.set_spacing(10);
 
-        let label = Label::new(Some(&format!(
-            "{}",
-            &crate::locales::LOCALE.connecting_to.replace("{}", ssid)
-        )));
+        let label = Label::new(Some(
+            &crate::locales::LOCALE.connecting_to.replace("{}", ssid),
+        ));
         content.pack_start(&label, false, false, 0);
 
         let password_entry = Entry::new();
@@ -516,8 +500,7 @@ impl WiFiPopup {
     }
 
     pub fn set_trigger_button<W: IsA<gtk::Widget>>(&self, button: &W) {
-        let mut btn = self.trigger_button.lock().unwrap();
-        *btn = Some(button.clone().upcast());
+        *self.trigger_button.borrow_mut() = Some(button.clone().upcast());
     }
 
     pub fn toggle(&self) {
@@ -537,13 +520,42 @@ impl WiFiPopup {
     }
 
     fn position_window(&self) {
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Bottom, true);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Right, true);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Left, false);
-        <Window as LayerShell>::set_anchor(&self.window, gtk_layer_shell::Edge::Top, false);
+        let trigger = self.trigger_button.borrow().clone();
+        crate::utils::anchor_popup(
+            &self.window,
+            self.panel_edge.get(),
+            true,
+            trigger.as_ref(),
+        );
+    }
+}
+
+impl crate::modules::PanelPopup for WiFiPopup {
+    fn toggle(&self) {
+        WiFiPopup::toggle(self);
+    }
+
+    fn set_trigger(&self, widget: &gtk::Widget) {
+        self.set_trigger_button(widget);
+    }
+
+    fn set_edge(&self, edge: PanelEdge) {
+        self.panel_edge.set(edge);
     }
+}
 
-    pub fn get_window(&self) -> &Window {
-        &self.window
+fn network_state() -> crate::ipc::Event {
+    let state = exec_command("nmcli -t -f STATE general").trim().to_string();
+    let ssid = exec_command("nmcli -t -f active,ssid dev wifi | grep '^yes:' | cut -d: -f2")
+        .trim()
+        .to_string();
+    crate::ipc::Event::Network {
+        state,
+        ssid: if ssid.is_empty() { None } else { Some(ssid) },
     }
 }
+
+// Publishes the connection state whenever NetworkManager reports a change.
+pub fn watch_events() {
+    crate::ipc::watch_command(&["nmcli", "monitor"], |_| true, network_state);
+}
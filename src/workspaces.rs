@@ -0,0 +1,222 @@
+use wayland_client::globals::GlobalList;
+use wayland_client::protocol::wl_output;
+use wayland_client::{Connection, Dispatch, QueueHandle, WEnum};
+
+use wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1::{self, ExtWorkspaceGroupHandleV1},
+    ext_workspace_handle_v1::{self, ExtWorkspaceHandleV1},
+    ext_workspace_manager_v1::{self, ExtWorkspaceManagerV1},
+};
+
+use crate::wayland::{AppData, UiEvent};
+
+// Workspaces and their groups from ext-workspace-v1. The compositor batches
+// changes and ends each batch with the manager's `done`; the UI is told then.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub handle: ExtWorkspaceHandleV1,
+    pub name: String,
+    pub active: bool,
+    pub urgent: bool,
+    pub hidden: bool,
+    pub can_activate: bool,
+}
+
+// A set of workspaces shown on the same outputs, usually one per monitor.
+#[derive(Debug, Clone)]
+pub struct WorkspaceGroup {
+    pub handle: ExtWorkspaceGroupHandleV1,
+    pub outputs: Vec<wl_output::WlOutput>,
+    // In the order the compositor assigned them.
+    pub workspaces: Vec<ExtWorkspaceHandleV1>,
+}
+
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    pub manager: Option<ExtWorkspaceManagerV1>,
+    pub groups: Vec<WorkspaceGroup>,
+    pub workspaces: Vec<Workspace>,
+}
+
+impl Workspaces {
+    pub fn bind(globals: &GlobalList, qh: &QueueHandle<AppData>) -> Self {
+        let manager = globals
+            .bind::<ExtWorkspaceManagerV1, _, _>(qh, 1..=1, ())
+            .ok();
+        eprintln!("[Wayland] ext-workspace: {}", manager.is_some());
+        Workspaces {
+            manager,
+            ..Workspaces::default()
+        }
+    }
+
+    // The visible workspaces of the groups on `output`, or of every group
+    // when `output` is None. Groups without outputs count as everywhere.
+    pub fn on_output(&self, output: Option<&wl_output::WlOutput>) -> Vec<Workspace> {
+        self.groups
+            .iter()
+            .filter(|g| match output {
+                Some(output) => g.outputs.is_empty() || g.outputs.contains(output),
+                None => true,
+            })
+            .flat_map(|g| g.workspaces.iter())
+            .filter_map(|handle| self.workspaces.iter().find(|w| &w.handle == handle))
+            .filter(|w| !w.hidden)
+            .cloned()
+            .collect()
+    }
+
+    pub fn activate(&self, workspace: &Workspace) {
+        let Some(manager) = self.manager.as_ref() else {
+            return;
+        };
+        if !workspace.can_activate {
+            return;
+        }
+        workspace.handle.activate();
+        manager.commit();
+    }
+
+    fn workspace_mut(&mut self, handle: &ExtWorkspaceHandleV1) -> Option<&mut Workspace> {
+        self.workspaces.iter_mut().find(|w| &w.handle == handle)
+    }
+
+    fn group_mut(&mut self, handle: &ExtWorkspaceGroupHandleV1) -> Option<&mut WorkspaceGroup> {
+        self.groups.iter_mut().find(|g| &g.handle == handle)
+    }
+}
+
+fn bits<T: Copy>(value: WEnum<T>, from_bits: impl Fn(u32) -> T) -> T {
+    match value {
+        WEnum::Value(value) => value,
+        WEnum::Unknown(raw) => from_bits(raw),
+    }
+}
+
+impl Dispatch<ExtWorkspaceManagerV1, ()> for AppData {
+    fn event(
+        state: &mut AppData,
+        _proxy: &ExtWorkspaceManagerV1,
+        event: ext_workspace_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<AppData>,
+    ) {
+        let mut workspaces = state.workspaces.lock().unwrap();
+        match event {
+            ext_workspace_manager_v1::Event::WorkspaceGroup { workspace_group } => {
+                workspaces.groups.push(WorkspaceGroup {
+                    handle: workspace_group,
+                    outputs: Vec::new(),
+                    workspaces: Vec::new(),
+                });
+            }
+            ext_workspace_manager_v1::Event::Workspace { workspace } => {
+                workspaces.workspaces.push(Workspace {
+                    handle: workspace,
+                    name: String::new(),
+                    active: false,
+                    urgent: false,
+                    hidden: false,
+                    can_activate: false,
+                });
+            }
+            ext_workspace_manager_v1::Event::Done => {
+                state.ui_sender.send(UiEvent::Workspaces).ok();
+            }
+            ext_workspace_manager_v1::Event::Finished => {
+                eprintln!("[Wayland] ext-workspace manager finished");
+                *workspaces = Workspaces::default();
+                state.ui_sender.send(UiEvent::Workspaces).ok();
+            }
+            _ => {}
+        }
+    }
+
+    wayland_client::event_created_child!(AppData, ExtWorkspaceManagerV1, [
+        ext_workspace_manager_v1::EVT_WORKSPACE_GROUP_OPCODE => (ExtWorkspaceGroupHandleV1, ()),
+        ext_workspace_manager_v1::EVT_WORKSPACE_OPCODE => (ExtWorkspaceHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ExtWorkspaceGroupHandleV1, ()> for AppData {
+    fn event(
+        state: &mut AppData,
+        proxy: &ExtWorkspaceGroupHandleV1,
+        event: ext_workspace_group_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<AppData>,
+    ) {
+        let mut workspaces = state.workspaces.lock().unwrap();
+        if let ext_workspace_group_handle_v1::Event::Removed = event {
+            workspaces.groups.retain(|g| &g.handle != proxy);
+            proxy.destroy();
+            return;
+        }
+        let Some(group) = workspaces.group_mut(proxy) else {
+            return;
+        };
+        match event {
+            ext_workspace_group_handle_v1::Event::OutputEnter { output }
+                if !group.outputs.contains(&output) =>
+            {
+                group.outputs.push(output);
+            }
+            ext_workspace_group_handle_v1::Event::OutputLeave { output } => {
+                group.outputs.retain(|o| o != &output);
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceEnter { workspace }
+                if !group.workspaces.contains(&workspace) =>
+            {
+                group.workspaces.push(workspace);
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceLeave { workspace } => {
+                group.workspaces.retain(|w| w != &workspace);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtWorkspaceHandleV1, ()> for AppData {
+    fn event(
+        state: &mut AppData,
+        proxy: &ExtWorkspaceHandleV1,
+        event: ext_workspace_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<AppData>,
+    ) {
+        let mut workspaces = state.workspaces.lock().unwrap();
+        if let ext_workspace_handle_v1::Event::Removed = event {
+            workspaces.workspaces.retain(|w| &w.handle != proxy);
+            for group in workspaces.groups.iter_mut() {
+                group.workspaces.retain(|w| w != proxy);
+            }
+            proxy.destroy();
+            return;
+        }
+        let Some(workspace) = workspaces.workspace_mut(proxy) else {
+            return;
+        };
+        match event {
+            ext_workspace_handle_v1::Event::Name { name } => workspace.name = name,
+            ext_workspace_handle_v1::Event::State { state } => {
+                let state = bits(state, ext_workspace_handle_v1::State::from_bits_truncate);
+                workspace.active = state.contains(ext_workspace_handle_v1::State::Active);
+                workspace.urgent = state.contains(ext_workspace_handle_v1::State::Urgent);
+                workspace.hidden = state.contains(ext_workspace_handle_v1::State::Hidden);
+            }
+            ext_workspace_handle_v1::Event::Capabilities { capabilities } => {
+                let capabilities = bits(
+                    capabilities,
+                    ext_workspace_handle_v1::WorkspaceCapabilities::from_bits_truncate,
+                );
+                workspace.can_activate =
+                    capabilities.contains(ext_workspace_handle_v1::WorkspaceCapabilities::Activate);
+            }
+            _ => {}
+        }
+    }
+}